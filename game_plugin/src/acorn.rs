@@ -0,0 +1,148 @@
+use crate::map::{Acorn, Collide, Map, MapSystemLabels, Slot, TILE_SIZE};
+use crate::pathfinding::Grid;
+use crate::player::{Player, PlayerSystemLabels};
+use crate::scenes::TriggerScene;
+use crate::{GameData, GameState, TiledMap};
+use bevy::prelude::*;
+use std::f32::consts::PI;
+
+const ACORN_SPEED: f32 = 190.;
+
+pub struct AcornPlugin;
+
+impl Plugin for AcornPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .after(MapSystemLabels::DrawMap)
+                .with_system(flee_from_player.system())
+                .with_system(catch_acorn.system().after(PlayerSystemLabels::MovePlayer)),
+        );
+    }
+}
+
+pub struct Fleeing {
+    slot: Slot,
+    next: Option<Slot>,
+}
+
+// Called at the end of the intro instead of letting the acorn fall down the hole
+pub fn release_acorn(
+    commands: &mut Commands,
+    acorn: Entity,
+    transform: &mut Transform,
+    current_map: &Map,
+) {
+    let goal = current_map.goal_position();
+    transform.translation.x = goal.0;
+    transform.translation.y = goal.1;
+    transform.rotation = Quat::IDENTITY;
+    transform.scale = Vec3::ONE;
+    commands.entity(acorn).insert(Fleeing {
+        slot: current_map.slot_from_position(goal.0, goal.1),
+        next: None,
+    });
+}
+
+fn flee_from_player(
+    time: Res<Time>,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    colliders: Query<&Collide>,
+    player_query: Query<&Transform, (With<Player>, Without<Fleeing>)>,
+    mut acorn_query: Query<(&mut Fleeing, &mut Transform)>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    let player_transform = match player_query.single() {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+    for (mut fleeing, mut transform) in acorn_query.iter_mut() {
+        if fleeing.next.is_none() {
+            let player_slot = current_map.slot_from_position(
+                player_transform.translation.x,
+                player_transform.translation.y,
+            );
+            let grid = Grid::new(&current_map.dimensions(), colliders.iter());
+            let distances = grid.distances(&player_slot);
+            let current = distances.get(&fleeing.slot).copied().unwrap_or(usize::MAX);
+            // never roll towards the squirrel; when cornered just wait
+            fleeing.next = grid
+                .neighbours(&fleeing.slot)
+                .into_iter()
+                .filter(|neighbour| {
+                    distances.get(neighbour).copied().unwrap_or(usize::MAX) > current
+                })
+                .max_by_key(|neighbour| distances.get(neighbour).copied().unwrap_or(usize::MAX));
+        }
+
+        if let Some(next) = fleeing.next.clone() {
+            let position = transform.translation.truncate();
+            let goal = Vec2::new(next.column as f32 * TILE_SIZE, next.row as f32 * TILE_SIZE);
+            let step = ACORN_SPEED * time.delta_seconds();
+            let to_goal = goal - position;
+            if to_goal.length() <= step {
+                transform.translation.x = goal.x;
+                transform.translation.y = goal.y;
+                fleeing.slot = next;
+                fleeing.next = None;
+            } else {
+                let direction = to_goal.normalize();
+                transform.translation.x += direction.x * step;
+                transform.translation.y += direction.y * step;
+                transform.rotate(Quat::from_rotation_z(
+                    -direction.x.signum() * 2. * PI * time.delta_seconds(),
+                ));
+            }
+        }
+    }
+}
+
+fn catch_acorn(
+    mut commands: Commands,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    maps: Res<Assets<TiledMap>>,
+    mut trigger_scene: EventWriter<TriggerScene>,
+    player_query: Query<&Transform, With<Player>>,
+    acorn_query: Query<(Entity, &Transform), (With<Acorn>, With<Fleeing>, Without<Player>)>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    let player_transform = match player_query.single() {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+    for (acorn, transform) in acorn_query.iter() {
+        if transform
+            .translation
+            .truncate()
+            .distance(player_transform.translation.truncate())
+            >= TILE_SIZE / 2.
+        {
+            continue;
+        }
+        commands.entity(acorn).despawn();
+        let caught = transform.translation.truncate();
+        // the squirrel takes the way down closest to the catch
+        let exit = current_map.exits(&maps).into_iter().min_by_key(|exit| {
+            let position = current_map.position_from_slot(exit.position.clone());
+            Vec2::from(position).distance(caught) as u32
+        });
+        if let Some(exit) = exit {
+            if let Some(scene) = current_map.exit_scene(
+                &exit,
+                (
+                    player_transform.translation.x,
+                    player_transform.translation.y,
+                ),
+                Some((caught.x, caught.y)),
+            ) {
+                trigger_scene.send(TriggerScene { scene });
+            }
+        }
+    }
+}
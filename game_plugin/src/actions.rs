@@ -1,3 +1,4 @@
+use crate::settings::Settings;
 use crate::GameState;
 use bevy::prelude::*;
 
@@ -5,16 +6,45 @@ pub struct ActionsPlugin;
 
 impl Plugin for ActionsPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<Actions>().add_system_set(
-            SystemSet::on_update(GameState::Playing).with_system(set_movement_actions.system()),
-        );
+        app.init_resource::<Actions>()
+            .init_resource::<ControlSettings>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(set_movement_actions.system())
+                    .with_system(set_step_actions.system()),
+            );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ControlScheme {
+    Free,
+    // one tile per key press
+    GridStep,
+}
+
+pub struct ControlSettings {
+    pub scheme: ControlScheme,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        ControlSettings {
+            scheme: ControlScheme::Free,
+        }
     }
 }
 
 #[derive(Default)]
 pub struct Actions {
     pub player_movement: Option<Vec2>,
+    pub player_step: Option<Vec2>,
     pub scip_scene: bool,
+    pub reset_blocks: bool,
+    pub dig: bool,
+    pub dash: bool,
+    pub hint: bool,
+    pub rewind: bool,
 }
 
 enum GameControl {
@@ -84,8 +114,15 @@ impl GameControl {
     }
 }
 
-fn set_movement_actions(mut actions: ResMut<Actions>, keyboard_input: Res<Input<KeyCode>>) {
-    if GameControl::Up.just_released(&keyboard_input)
+fn set_movement_actions(
+    mut actions: ResMut<Actions>,
+    controls: Res<ControlSettings>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+) {
+    if controls.scheme != ControlScheme::Free {
+        actions.player_movement = None;
+    } else if GameControl::Up.just_released(&keyboard_input)
         || GameControl::Up.pressed(&keyboard_input)
         || GameControl::Left.just_released(&keyboard_input)
         || GameControl::Left.pressed(&keyboard_input)
@@ -139,5 +176,48 @@ fn set_movement_actions(mut actions: ResMut<Actions>, keyboard_input: Res<Input<
     } else {
         actions.player_movement = None;
     }
-    actions.scip_scene = keyboard_input.just_pressed(KeyCode::Space);
+    actions.reset_blocks = keyboard_input.just_pressed(KeyCode::R);
+    actions.dig = keyboard_input.pressed(KeyCode::F);
+    actions.hint = keyboard_input.just_pressed(KeyCode::H);
+    actions.rewind = keyboard_input.pressed(KeyCode::Back);
+    actions.dash = keyboard_input.just_pressed(KeyCode::LShift)
+        || keyboard_input.just_pressed(KeyCode::RShift)
+        || gamepad_buttons
+            .get_just_pressed()
+            .any(|button| button.1 == GamepadButtonType::RightTrigger);
+}
+
+fn set_step_actions(
+    time: Res<Time>,
+    mut actions: ResMut<Actions>,
+    controls: Res<ControlSettings>,
+    settings: Res<Settings>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut repeat: Local<Timer>,
+) {
+    actions.player_step = None;
+    if controls.scheme != ControlScheme::GridStep {
+        return;
+    }
+    let directions = [
+        (GameControl::Up, Vec2::new(0., 1.)),
+        (GameControl::Down, Vec2::new(0., -1.)),
+        (GameControl::Left, Vec2::new(-1., 0.)),
+        (GameControl::Right, Vec2::new(1., 0.)),
+    ];
+    if let Some((_, direction)) = directions
+        .iter()
+        .find(|(control, _)| control.just_pressed(&keyboard_input))
+    {
+        *repeat = Timer::new(settings.step_repeat(), true);
+        actions.player_step = Some(*direction);
+    } else if let Some((_, direction)) = directions
+        .iter()
+        .find(|(control, _)| control.pressed(&keyboard_input))
+    {
+        repeat.tick(time.delta());
+        if repeat.just_finished() {
+            actions.player_step = Some(*direction);
+        }
+    }
 }
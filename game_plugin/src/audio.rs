@@ -1,3 +1,4 @@
+use crate::settings::Settings;
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_kira_audio::{Audio, AudioChannel, AudioPlugin, AudioSource};
@@ -10,17 +11,18 @@ impl Plugin for InternalAudioPlugin {
             effects: AudioChannel::new("effects".to_owned()),
             background: AudioChannel::new("background".to_owned()),
         })
+        .init_resource::<BackgroundMusic>()
         .add_plugin(AudioPlugin)
         .add_event::<AudioEffect>()
         .add_event::<BackgroundAudio>()
         .add_event::<ResumeBackground>()
         .add_event::<PauseBackground>()
         .add_event::<StopAudioEffects>()
-        .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(start_audio.system()))
         .add_system_set(
             SystemSet::new()
-                .with_system(play_effect.system())
-                .with_system(play_background.system())
+                .with_system(apply_volume.system().label(AudioSystemLabels::Volume))
+                .with_system(play_effect.system().after(AudioSystemLabels::Volume))
+                .with_system(play_background.system().after(AudioSystemLabels::Volume))
                 .with_system(resume_background.system())
                 .with_system(stop_effect.system())
                 .with_system(pause_background.system()),
@@ -29,6 +31,17 @@ impl Plugin for InternalAudioPlugin {
     }
 }
 
+#[derive(SystemLabel, Clone, Hash, Debug, Eq, PartialEq)]
+enum AudioSystemLabels {
+    Volume,
+}
+
+// Whether the background channel is currently paused, e.g. by a scene
+#[derive(Default)]
+pub struct BackgroundMusic {
+    pub paused: bool,
+}
+
 pub struct PauseBackground;
 pub struct ResumeBackground;
 pub struct StopAudioEffects;
@@ -46,9 +59,13 @@ struct AudioChannels {
     background: AudioChannel,
 }
 
-fn start_audio(audio: Res<Audio>, channels: Res<AudioChannels>) {
-    audio.set_volume_in_channel(0.4, &channels.effects);
-    audio.set_volume_in_channel(0.4, &channels.background);
+// Runs once on startup, before anything is played, and again whenever the settings change
+fn apply_volume(audio: Res<Audio>, channels: Res<AudioChannels>, settings: Res<Settings>) {
+    if !settings.is_changed() {
+        return;
+    }
+    audio.set_volume_in_channel(settings.effects_volume(), &channels.effects);
+    audio.set_volume_in_channel(settings.music_volume(), &channels.background);
 }
 
 fn stop_audio(audio: Res<Audio>, channels: Res<AudioChannels>) {
@@ -79,8 +96,10 @@ fn play_background(
     mut events: EventReader<BackgroundAudio>,
     audio: Res<Audio>,
     channels: Res<AudioChannels>,
+    mut music: ResMut<BackgroundMusic>,
 ) {
     for background in events.iter() {
+        music.paused = false;
         audio.stop_channel(&channels.background);
         for handle in &background.handles {
             audio.play_looped_in_channel(handle.clone(), &channels.background);
@@ -92,8 +111,10 @@ fn resume_background(
     mut events: EventReader<ResumeBackground>,
     audio: Res<Audio>,
     channels: Res<AudioChannels>,
+    mut music: ResMut<BackgroundMusic>,
 ) {
     for _event in events.iter() {
+        music.paused = false;
         audio.resume_channel(&channels.background);
     }
 }
@@ -102,8 +123,10 @@ fn pause_background(
     mut events: EventReader<PauseBackground>,
     audio: Res<Audio>,
     channels: Res<AudioChannels>,
+    mut music: ResMut<BackgroundMusic>,
 ) {
     for _event in events.iter() {
+        music.paused = true;
         audio.pause_channel(&channels.background);
     }
 }
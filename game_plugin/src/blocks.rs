@@ -0,0 +1,233 @@
+use crate::actions::Actions;
+use crate::audio::AudioEffect;
+use crate::loading::{AudioAssets, TextureAssets};
+use crate::map::{Collide, LevelEntity, Map, MapSystemLabels, Slot, TILE_SIZE};
+use crate::player::Player;
+use crate::{GameData, GameState, TiledMap};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+
+pub const BLOCK_Z: f32 = 3.;
+const BLOCK_TINT: Color = Color::rgb(0.8, 0.6, 0.4);
+const PUSH_DURATION: Duration = Duration::from_millis(150);
+
+pub struct BlocksPlugin;
+
+impl Plugin for BlocksPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<PushBlock>()
+            .add_event::<RestoreBlocks>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .after(MapSystemLabels::DrawMap)
+                    .with_system(draw_blocks.system())
+                    .with_system(push_blocks.system())
+                    .with_system(animate_blocks.system())
+                    .with_system(reset_blocks.system())
+                    .with_system(restore_blocks.system()),
+            );
+    }
+}
+
+pub struct PushBlock {
+    pub block: Entity,
+    pub from: Slot,
+}
+
+pub struct Block {
+    pub slot: Slot,
+    pub home: Slot,
+}
+
+// Sent when loading a save with the blocks that were pushed away from home
+pub struct RestoreBlocks {
+    pub moved: Vec<(Slot, Slot)>,
+}
+
+struct BlockMove {
+    from: Vec3,
+    to: Vec3,
+    timer: Timer,
+}
+
+fn draw_blocks(
+    mut commands: Commands,
+    current_map: Res<Map>,
+    maps: Res<Assets<TiledMap>>,
+    textures: Res<TextureAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    blocks: Query<Entity, With<Block>>,
+) {
+    if !current_map.is_added() && !current_map.is_changed() {
+        return;
+    }
+    for entity in blocks.iter() {
+        commands.entity(entity).despawn();
+    }
+    for position in current_map.blocks(&maps) {
+        let slot = current_map.tiled_slot_to_bevy_slot(position);
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(ColorMaterial::modulated_texture(
+                    textures.texture_wall_up.clone(),
+                    BLOCK_TINT,
+                )),
+                transform: Transform::from_translation(Vec3::new(
+                    slot.column as f32 * TILE_SIZE,
+                    slot.row as f32 * TILE_SIZE,
+                    BLOCK_Z,
+                )),
+                ..Default::default()
+            })
+            .insert(Collide {
+                x: slot.column,
+                y: slot.row,
+            })
+            .insert(LevelEntity)
+            .insert(Block {
+                slot: slot.clone(),
+                home: slot,
+            });
+    }
+}
+
+fn push_blocks(
+    mut commands: Commands,
+    current_map: Res<Map>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut push_events: EventReader<PushBlock>,
+    mut blocks: Query<(&mut Block, &mut Collide, &Transform, Option<&BlockMove>)>,
+    colliders: Query<&Collide, Without<Block>>,
+) {
+    for push in push_events.iter() {
+        let dimensions = current_map.dimensions();
+        // a sliding block already holds the slot it is sliding to
+        let mut targets: Vec<(usize, usize)> = blocks
+            .iter_mut()
+            .map(|(block, _, _, _)| (block.slot.column, block.slot.row))
+            .collect();
+        targets.extend(colliders.iter().map(|collide| (collide.x, collide.y)));
+        if let Ok((mut block, mut collide, transform, None)) = blocks.get_mut(push.block) {
+            let column_step = block.slot.column as i32 - push.from.column as i32;
+            let row_step = block.slot.row as i32 - push.from.row as i32;
+            // only push along one axis
+            if column_step.abs() + row_step.abs() != 1 {
+                continue;
+            }
+            let column = block.slot.column as i32 + column_step;
+            let row = block.slot.row as i32 + row_step;
+            if column < 0
+                || row < 0
+                || column as usize >= dimensions.columns
+                || row as usize >= dimensions.rows
+                || targets.contains(&(column as usize, row as usize))
+            {
+                continue;
+            }
+            block.slot = Slot {
+                column: column as usize,
+                row: row as usize,
+            };
+            collide.x = block.slot.column;
+            collide.y = block.slot.row;
+            commands.entity(push.block).insert(BlockMove {
+                from: transform.translation,
+                to: Vec3::new(
+                    block.slot.column as f32 * TILE_SIZE,
+                    block.slot.row as f32 * TILE_SIZE,
+                    BLOCK_Z,
+                ),
+                timer: Timer::new(PUSH_DURATION, false),
+            });
+            audio_effect.send(AudioEffect {
+                handle: audio_assets.wall_moving.clone(),
+            });
+        }
+    }
+}
+
+fn animate_blocks(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_state: Res<GameData>,
+    mut blocks: Query<(Entity, &mut BlockMove, &mut Transform)>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    for (entity, mut block_move, mut transform) in blocks.iter_mut() {
+        block_move.timer.tick(time.delta());
+        transform.translation = block_move
+            .from
+            .lerp(block_move.to, block_move.timer.percent());
+        if block_move.timer.finished() {
+            commands.entity(entity).remove::<BlockMove>();
+        }
+    }
+}
+
+fn reset_blocks(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut blocks: Query<(Entity, &mut Block, &mut Collide, &mut Transform), Without<Player>>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if !actions.reset_blocks || game_state.frozen {
+        return;
+    }
+    if let Ok(player_transform) = player_query.single() {
+        let player_slot = current_map.slot_from_position(
+            player_transform.translation.x,
+            player_transform.translation.y,
+        );
+        if blocks
+            .iter_mut()
+            .any(|(_, block, _, _)| block.home == player_slot)
+        {
+            audio_effect.send(AudioEffect {
+                handle: audio_assets.no_no.clone(),
+            });
+            return;
+        }
+    }
+    for (entity, mut block, mut collide, mut transform) in blocks.iter_mut() {
+        block.slot = block.home.clone();
+        collide.x = block.slot.column;
+        collide.y = block.slot.row;
+        transform.translation = Vec3::new(
+            block.slot.column as f32 * TILE_SIZE,
+            block.slot.row as f32 * TILE_SIZE,
+            BLOCK_Z,
+        );
+        commands.entity(entity).remove::<BlockMove>();
+    }
+}
+
+fn restore_blocks(
+    mut commands: Commands,
+    mut restore_events: EventReader<RestoreBlocks>,
+    mut blocks: Query<(Entity, &mut Block, &mut Collide, &mut Transform)>,
+) {
+    for restore in restore_events.iter() {
+        for (entity, mut block, mut collide, mut transform) in blocks.iter_mut() {
+            let slot = match restore.moved.iter().find(|(home, _)| *home == block.home) {
+                Some((_, slot)) => slot.clone(),
+                None => continue,
+            };
+            collide.x = slot.column;
+            collide.y = slot.row;
+            transform.translation = Vec3::new(
+                slot.column as f32 * TILE_SIZE,
+                slot.row as f32 * TILE_SIZE,
+                BLOCK_Z,
+            );
+            block.slot = slot;
+            commands.entity(entity).remove::<BlockMove>();
+        }
+    }
+}
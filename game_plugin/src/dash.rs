@@ -0,0 +1,74 @@
+use crate::actions::Actions;
+use crate::audio::AudioEffect;
+use crate::loading::AudioAssets;
+use crate::player::PlayerSystemLabels;
+use crate::{GameData, GameState};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+
+pub const DASH_SPEED_FACTOR: f32 = 2.5;
+const DASH_DURATION: Duration = Duration::from_millis(250);
+const DASH_COST: f32 = 0.35;
+// stamina regained per second
+const STAMINA_REGENERATION: f32 = 0.2;
+
+pub struct DashPlugin;
+
+impl Plugin for DashPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Stamina>().add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(dash.system().before(PlayerSystemLabels::MovePlayer)),
+        );
+    }
+}
+
+pub struct Stamina {
+    pub value: f32,
+    dash: Option<Timer>,
+}
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Stamina {
+            value: 1.,
+            dash: None,
+        }
+    }
+}
+
+impl Stamina {
+    pub fn dashing(&self) -> bool {
+        self.dash.is_some()
+    }
+}
+
+fn dash(
+    time: Res<Time>,
+    actions: Res<Actions>,
+    game_state: Res<GameData>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut stamina: ResMut<Stamina>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    if let Some(timer) = stamina.dash.as_mut() {
+        timer.tick(time.delta());
+        if timer.finished() {
+            stamina.dash = None;
+        }
+        return;
+    }
+    if stamina.value < 1. {
+        stamina.value = (stamina.value + STAMINA_REGENERATION * time.delta_seconds()).min(1.);
+    }
+    if actions.dash && actions.player_movement.is_some() && stamina.value >= DASH_COST {
+        stamina.value -= DASH_COST;
+        stamina.dash = Some(Timer::new(DASH_DURATION, false));
+        audio_effect.send(AudioEffect {
+            handle: audio_assets.puh.clone(),
+        });
+    }
+}
@@ -0,0 +1,271 @@
+use crate::actions::Actions;
+use crate::audio::AudioEffect;
+use crate::loading::AudioAssets;
+use crate::map::{Collide, Map, MapSystemLabels, MapTile, Slot, TILE_SIZE};
+use crate::player::{Player, PlayerSystemLabels};
+use crate::{GameData, GameState};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use std::f32::consts::PI;
+
+const DIG_DURATION: Duration = Duration::from_millis(600);
+const SCRATCH_INTERVAL: Duration = Duration::from_millis(150);
+const BONK_DURATION: Duration = Duration::from_millis(300);
+
+pub struct DiggingPlugin;
+
+impl Plugin for DiggingPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Digs>()
+            .add_event::<RestoreDigs>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .after(MapSystemLabels::DrawMap)
+                    .with_system(reset_digs.system())
+                    .with_system(dig.system().after(PlayerSystemLabels::MovePlayer))
+                    .with_system(bonk_walls.system())
+                    .with_system(restore_digs.system()),
+            );
+    }
+}
+
+#[derive(Default)]
+pub struct Digs {
+    pub left: usize,
+    // walls dug out in the current level
+    pub dug: Vec<Slot>,
+    current: Option<DigTarget>,
+    refused: bool,
+}
+
+struct DigTarget {
+    tile: Entity,
+    facing: Quat,
+    timer: Timer,
+    scratch: Timer,
+}
+
+// Sent when loading a save
+pub struct RestoreDigs {
+    pub left: usize,
+    pub dug: Vec<Slot>,
+}
+
+struct Bonk {
+    timer: Timer,
+}
+
+fn reset_digs(current_map: Res<Map>, mut digs: ResMut<Digs>) {
+    if !current_map.is_changed() {
+        return;
+    }
+    digs.left = current_map.digs();
+    digs.dug.clear();
+    digs.current = None;
+    digs.refused = false;
+}
+
+// The slot next to the squirrel in the direction it is looking
+fn facing_slot(current_map: &Map, transform: &Transform) -> Option<Slot> {
+    let slot = current_map.slot_from_position(transform.translation.x, transform.translation.y);
+    let facing = facing(transform);
+    let column = slot.column as i32 + facing.x as i32;
+    let row = slot.row as i32 + facing.y as i32;
+    if column < 0 || row < 0 {
+        return None;
+    }
+    Some(Slot {
+        column: column as usize,
+        row: row as usize,
+    })
+}
+
+// The squirrel's look direction snapped to one axis
+fn facing(transform: &Transform) -> Vec2 {
+    let facing = -(transform.rotation * Vec3::Y);
+    if facing.x.abs() > facing.y.abs() {
+        Vec2::new(facing.x.signum(), 0.)
+    } else {
+        Vec2::new(0., facing.y.signum())
+    }
+}
+
+fn dig(
+    mut commands: Commands,
+    time: Res<Time>,
+    actions: Res<Actions>,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut digs: ResMut<Digs>,
+    mut walls: Query<(Entity, &MapTile, &mut Transform), (With<Collide>, Without<Player>)>,
+    floor: Query<(&MapTile, &Handle<ColorMaterial>), Without<Collide>>,
+    mut wall_materials: Query<&mut Handle<ColorMaterial>, With<Collide>>,
+    mut player_query: Query<&mut Transform, With<Player>>,
+) {
+    if !actions.dig || game_state.frozen {
+        if let Some(target) = digs.current.take() {
+            if let Ok((_, tile, mut transform)) = walls.get_mut(target.tile) {
+                reset_tile_transform(tile, &mut transform);
+            }
+        }
+        if digs.refused {
+            digs.refused = false;
+        }
+        return;
+    }
+    if digs.refused {
+        return;
+    }
+    let mut player_transform = match player_query.single_mut() {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+
+    if digs.current.is_none() {
+        let slot = match facing_slot(&current_map, &player_transform) {
+            Some(slot) => slot,
+            None => return,
+        };
+        let wall = walls
+            .iter_mut()
+            .find(|(_, tile, _)| tile.column == slot.column && tile.row == slot.row)
+            .map(|(entity, tile, _)| (entity, tile.tile.diggable));
+        let (entity, diggable) = match wall {
+            Some(wall) => wall,
+            None => return,
+        };
+        let dimensions = current_map.dimensions();
+        let border = slot.column == 0
+            || slot.row == 0
+            || slot.column + 1 >= dimensions.columns
+            || slot.row + 1 >= dimensions.rows;
+        if !diggable || border || digs.left == 0 {
+            digs.refused = true;
+            commands.entity(entity).insert(Bonk {
+                timer: Timer::new(BONK_DURATION, false),
+            });
+            audio_effect.send(AudioEffect {
+                handle: audio_assets.no_no.clone(),
+            });
+            return;
+        }
+        let facing = facing(&player_transform);
+        digs.current = Some(DigTarget {
+            tile: entity,
+            facing: Quat::from_rotation_z(-1. * facing.angle_between(Vec2::new(0., 1.)) + PI),
+            timer: Timer::new(DIG_DURATION, false),
+            scratch: Timer::new(SCRATCH_INTERVAL, true),
+        });
+    }
+
+    let target = digs.current.as_mut().unwrap();
+    target.timer.tick(time.delta());
+    target.scratch.tick(time.delta());
+    if target.scratch.just_finished() {
+        audio_effect.send(AudioEffect {
+            handle: audio_assets.tick.clone(),
+        });
+    }
+    let progress = target.timer.percent();
+    let tile_entity = target.tile;
+    let finished = target.timer.finished();
+    player_transform.rotation =
+        target.facing * Quat::from_rotation_z((progress * 8. * PI).sin() * 0.2);
+
+    let (tile, mut transform) = match walls.get_mut(tile_entity) {
+        Ok((_, tile, transform)) => (tile, transform),
+        Err(_) => {
+            digs.current = None;
+            return;
+        }
+    };
+    if !finished {
+        transform.scale = Vec3::splat(1. - 0.3 * progress);
+        transform.translation.x = tile.column as f32 * TILE_SIZE + (progress * 40.).sin() * 3.;
+        return;
+    }
+    reset_tile_transform(tile, &mut transform);
+    dig_out(
+        &mut commands,
+        tile_entity,
+        tile,
+        &floor,
+        &mut wall_materials,
+    );
+    audio_effect.send(AudioEffect {
+        handle: audio_assets.wall_moving.clone(),
+    });
+    digs.dug.push(Slot {
+        column: tile.column,
+        row: tile.row,
+    });
+    digs.left -= 1;
+    digs.current = None;
+    // one dig per press
+    digs.refused = true;
+}
+
+// The wall takes the look of the floor below it and can be walked over
+fn dig_out(
+    commands: &mut Commands,
+    wall: Entity,
+    tile: &MapTile,
+    floor: &Query<(&MapTile, &Handle<ColorMaterial>), Without<Collide>>,
+    wall_materials: &mut Query<&mut Handle<ColorMaterial>, With<Collide>>,
+) {
+    if let Some((_, floor_material)) = floor
+        .iter()
+        .find(|(floor, _)| floor.column == tile.column && floor.row == tile.row)
+    {
+        if let Ok(mut material) = wall_materials.get_mut(wall) {
+            *material = floor_material.clone();
+        }
+    }
+    commands.entity(wall).remove::<Collide>();
+}
+
+fn restore_digs(
+    mut commands: Commands,
+    mut restore_events: EventReader<RestoreDigs>,
+    mut digs: ResMut<Digs>,
+    walls: Query<(Entity, &MapTile), With<Collide>>,
+    floor: Query<(&MapTile, &Handle<ColorMaterial>), Without<Collide>>,
+    mut wall_materials: Query<&mut Handle<ColorMaterial>, With<Collide>>,
+) {
+    for restore in restore_events.iter() {
+        for (entity, tile) in walls.iter() {
+            let slot = Slot {
+                column: tile.column,
+                row: tile.row,
+            };
+            if restore.dug.contains(&slot) {
+                dig_out(&mut commands, entity, tile, &floor, &mut wall_materials);
+            }
+        }
+        digs.left = restore.left;
+        digs.dug = restore.dug.clone();
+    }
+}
+
+fn reset_tile_transform(tile: &MapTile, transform: &mut Transform) {
+    transform.scale = Vec3::ONE;
+    transform.translation.x = tile.column as f32 * TILE_SIZE;
+}
+
+fn bonk_walls(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut walls: Query<(Entity, &MapTile, &mut Bonk, &mut Transform)>,
+) {
+    for (entity, tile, mut bonk, mut transform) in walls.iter_mut() {
+        bonk.timer.tick(time.delta());
+        let shake = (bonk.timer.elapsed_secs() * 80.).sin() * 4. * bonk.timer.percent_left();
+        transform.translation.x = tile.column as f32 * TILE_SIZE + shake;
+        if bonk.timer.finished() {
+            reset_tile_transform(tile, &mut transform);
+            commands.entity(entity).remove::<Bonk>();
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::audio::AudioEffect;
+use crate::hazards::Hazard;
+use crate::loading::{AudioAssets, TextureAssets};
+use crate::map::{Collide, Map, MapSystemLabels, MapTile, TILE_SIZE};
+use crate::player::{Player, PlayerSystemLabels};
+use crate::{GameData, GameState};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+
+pub const PLATFORM_Z: f32 = 0.5;
+pub const PLATFORM_TINT: Color = Color::rgb(0.55, 0.45, 0.4);
+pub const CRUMBLING_TINT: Color = Color::rgb(0.85, 0.7, 0.55);
+const HOLE_TINT: Color = Color::rgb(0.1, 0.08, 0.08);
+const PLATFORM_SPEED: f32 = 90.;
+const CRUMBLE_DURATION: Duration = Duration::from_secs(1);
+
+pub struct DynamicTilesPlugin;
+
+impl Plugin for DynamicTilesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .after(MapSystemLabels::DrawMap)
+                .with_system(
+                    move_platforms
+                        .system()
+                        .before(PlayerSystemLabels::MovePlayer),
+                )
+                .with_system(step_on_crumbling_tiles.system())
+                .with_system(crumble_tiles.system()),
+        );
+    }
+}
+
+pub struct Platform {
+    path: Vec<Vec2>,
+    target: usize,
+    forward: bool,
+}
+
+impl Platform {
+    pub fn new(path: Vec<Vec2>) -> Self {
+        Platform {
+            target: if path.len() > 1 { 1 } else { 0 },
+            path,
+            forward: true,
+        }
+    }
+
+    fn advance(&mut self) {
+        if self.path.len() < 2 {
+            return;
+        }
+        if self.forward && self.target + 1 == self.path.len() {
+            self.forward = false;
+        } else if !self.forward && self.target == 0 {
+            self.forward = true;
+        }
+        if self.forward {
+            self.target += 1;
+        } else {
+            self.target -= 1;
+        }
+    }
+}
+
+pub struct CrumblingTile;
+
+struct Crumbling {
+    timer: Timer,
+}
+
+// Is something at `position` standing on the platform?
+pub fn carries(platform: &Transform, position: Vec2) -> bool {
+    (platform.translation.x - position.x).abs() < TILE_SIZE / 2.
+        && (platform.translation.y - position.y).abs() < TILE_SIZE / 2.
+}
+
+fn move_platforms(
+    time: Res<Time>,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    mut platforms: Query<(&mut Platform, &mut Transform), Without<Player>>,
+    mut player_query: Query<&mut Transform, With<Player>>,
+    colliders: Query<&Collide>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    for (mut platform, mut transform) in platforms.iter_mut() {
+        let position = transform.translation.truncate();
+        let target = platform.path[platform.target];
+        let step = PLATFORM_SPEED * time.delta_seconds();
+        let to_target = target - position;
+        let movement = if to_target.length() <= step {
+            platform.advance();
+            to_target
+        } else {
+            to_target.normalize() * step
+        };
+        if let Ok(mut player_transform) = player_query.single_mut() {
+            let player_position = player_transform.translation.truncate();
+            if carries(&transform, player_position) {
+                let carried_to = player_position + movement;
+                let slot = current_map.slot_from_position(carried_to.x, carried_to.y);
+                // the squirrel gets scraped off by walls instead of being pushed into them
+                if !colliders
+                    .iter()
+                    .any(|collide| collide.x == slot.column && collide.y == slot.row)
+                {
+                    player_transform.translation.x = carried_to.x;
+                    player_transform.translation.y = carried_to.y;
+                }
+            }
+        }
+        transform.translation.x += movement.x;
+        transform.translation.y += movement.y;
+    }
+}
+
+fn step_on_crumbling_tiles(
+    mut commands: Commands,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    tiles: Query<(Entity, &MapTile), (With<CrumblingTile>, Without<Crumbling>)>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    if let Ok(player_transform) = player_query.single() {
+        let player_slot = current_map.slot_from_position(
+            player_transform.translation.x,
+            player_transform.translation.y,
+        );
+        for (entity, tile) in tiles.iter() {
+            if tile.column == player_slot.column && tile.row == player_slot.row {
+                commands.entity(entity).insert(Crumbling {
+                    timer: Timer::new(CRUMBLE_DURATION, false),
+                });
+            }
+        }
+    }
+}
+
+fn crumble_tiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_state: Res<GameData>,
+    textures: Res<TextureAssets>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut tiles: Query<(
+        Entity,
+        &MapTile,
+        &mut Crumbling,
+        &mut Transform,
+        &mut Handle<ColorMaterial>,
+    )>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    for (entity, tile, mut crumbling, mut transform, mut material) in tiles.iter_mut() {
+        crumbling.timer.tick(time.delta());
+        let shake = (crumbling.timer.elapsed_secs() * 60.).sin() * 3. * crumbling.timer.percent();
+        transform.translation.x = tile.column as f32 * TILE_SIZE + shake;
+        if !crumbling.timer.finished() {
+            continue;
+        }
+        transform.translation.x = tile.column as f32 * TILE_SIZE;
+        *material = materials.add(ColorMaterial::modulated_texture(
+            textures.texture_wall_down.clone(),
+            HOLE_TINT,
+        ));
+        commands
+            .entity(entity)
+            .remove::<Crumbling>()
+            .remove::<CrumblingTile>()
+            .insert(Hazard)
+            .insert(Collide {
+                x: tile.column,
+                y: tile.row,
+            });
+        audio_effect.send(AudioEffect {
+            handle: audio_assets.wall_moving.clone(),
+        });
+    }
+}
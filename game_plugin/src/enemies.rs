@@ -0,0 +1,248 @@
+use crate::loading::TextureAssets;
+use crate::map::{Collide, LevelEntity, Map, MapSystemLabels, Slot, TILE_SIZE};
+use crate::pathfinding::Grid;
+use crate::player::{calc_camera_position, Player, PlayerSystemLabels};
+use crate::scenes::{CutScene, TriggerScene};
+use crate::{GameData, GameState, TiledMap};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::f32::consts::PI;
+
+pub const ENEMY_Z: f32 = 4.;
+
+pub struct EnemiesPlugin;
+
+impl Plugin for EnemiesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .after(MapSystemLabels::DrawMap)
+                .with_system(draw_enemies.system())
+                .with_system(move_enemies.system().after(PlayerSystemLabels::MovePlayer))
+                .with_system(catch_player.system()),
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+pub enum EnemyKind {
+    Fox,
+    Owl,
+    Mole,
+}
+
+impl EnemyKind {
+    fn tint(&self) -> Color {
+        match self {
+            EnemyKind::Fox => Color::rgb(1., 0.45, 0.1),
+            EnemyKind::Owl => Color::rgb(0.6, 0.55, 0.45),
+            EnemyKind::Mole => Color::rgb(0.35, 0.3, 0.3),
+        }
+    }
+
+    fn speed(&self) -> f32 {
+        match self {
+            EnemyKind::Fox => 170.,
+            EnemyKind::Owl => 140.,
+            EnemyKind::Mole => 100.,
+        }
+    }
+
+    fn sight(&self) -> f32 {
+        match self {
+            EnemyKind::Fox => 6. * TILE_SIZE,
+            EnemyKind::Owl => 9. * TILE_SIZE,
+            EnemyKind::Mole => 3. * TILE_SIZE,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EnemySpawn {
+    pub kind: EnemyKind,
+    pub patrol: Vec<Slot>,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum EnemyState {
+    Patrol,
+    Pursuit { last_seen: Slot },
+}
+
+pub struct Enemy {
+    pub kind: EnemyKind,
+    pub patrol: Vec<Slot>,
+    pub patrol_index: usize,
+    pub state: EnemyState,
+    pub slot: Slot,
+    pub next: Option<Slot>,
+}
+
+impl Enemy {
+    pub fn reset(&mut self, transform: &mut Transform) {
+        self.slot = self.patrol[0].clone();
+        self.next = None;
+        self.patrol_index = 0;
+        self.state = EnemyState::Patrol;
+        transform.translation.x = self.slot.column as f32 * TILE_SIZE;
+        transform.translation.y = self.slot.row as f32 * TILE_SIZE;
+    }
+}
+
+fn draw_enemies(
+    mut commands: Commands,
+    current_map: Res<Map>,
+    maps: Res<Assets<TiledMap>>,
+    textures: Res<TextureAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    enemies: Query<Entity, With<Enemy>>,
+) {
+    if !current_map.is_added() && !current_map.is_changed() {
+        return;
+    }
+    for entity in enemies.iter() {
+        commands.entity(entity).despawn();
+    }
+    for spawn in current_map.enemies(&maps) {
+        let patrol: Vec<Slot> = spawn
+            .patrol
+            .iter()
+            .map(|slot| current_map.tiled_slot_to_bevy_slot(slot.clone()))
+            .collect();
+        let start = patrol[0].clone();
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(ColorMaterial::modulated_texture(
+                    textures.texture_player.clone(),
+                    spawn.kind.tint(),
+                )),
+                transform: Transform::from_translation(Vec3::new(
+                    start.column as f32 * TILE_SIZE,
+                    start.row as f32 * TILE_SIZE,
+                    ENEMY_Z,
+                )),
+                ..Default::default()
+            })
+            .insert(LevelEntity)
+            .insert(Enemy {
+                kind: spawn.kind,
+                patrol,
+                patrol_index: 0,
+                state: EnemyState::Patrol,
+                slot: start,
+                next: None,
+            });
+    }
+}
+
+fn move_enemies(
+    time: Res<Time>,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    colliders: Query<&Collide>,
+    player_query: Query<&Transform, (With<Player>, Without<Enemy>)>,
+    mut enemies: Query<(&mut Enemy, &mut Transform)>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    let player_transform = match player_query.single() {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+    let player_position = player_transform.translation.truncate();
+    let player_slot = current_map.slot_from_position(player_position.x, player_position.y);
+    let grid = Grid::new(&current_map.dimensions(), colliders.iter());
+    for (mut enemy, mut transform) in enemies.iter_mut() {
+        let position = transform.translation.truncate();
+        if position.distance(player_position) < enemy.kind.sight()
+            && grid.line_of_sight(position, player_position)
+        {
+            enemy.state = EnemyState::Pursuit {
+                last_seen: player_slot.clone(),
+            };
+        }
+
+        if enemy.next.is_none() {
+            let target = match &enemy.state {
+                EnemyState::Pursuit { last_seen } => {
+                    if last_seen == &enemy.slot {
+                        enemy.state = EnemyState::Patrol;
+                        enemy.patrol[enemy.patrol_index].clone()
+                    } else {
+                        last_seen.clone()
+                    }
+                }
+                EnemyState::Patrol => {
+                    if enemy.patrol[enemy.patrol_index] == enemy.slot {
+                        enemy.patrol_index = (enemy.patrol_index + 1) % enemy.patrol.len();
+                    }
+                    enemy.patrol[enemy.patrol_index].clone()
+                }
+            };
+            enemy.next = grid.next_step(&enemy.slot, &target);
+            // lost track of the squirrel
+            if enemy.next.is_none() && enemy.state != EnemyState::Patrol {
+                enemy.state = EnemyState::Patrol;
+            }
+        }
+
+        if let Some(next) = enemy.next.clone() {
+            let goal = Vec2::new(next.column as f32 * TILE_SIZE, next.row as f32 * TILE_SIZE);
+            let step = enemy.kind.speed() * time.delta_seconds();
+            let to_goal = goal - position;
+            if to_goal.length() <= step {
+                transform.translation.x = goal.x;
+                transform.translation.y = goal.y;
+                enemy.slot = next;
+                enemy.next = None;
+            } else {
+                let direction = to_goal.normalize();
+                transform.translation.x += direction.x * step;
+                transform.translation.y += direction.y * step;
+                transform.rotation =
+                    Quat::from_rotation_z(-1. * direction.angle_between(Vec2::new(0., 1.)) + PI);
+            }
+        }
+    }
+}
+
+fn catch_player(
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    windows: Res<Windows>,
+    player_query: Query<&Transform, With<Player>>,
+    enemies: Query<&Transform, With<Enemy>>,
+    mut trigger_scene: EventWriter<TriggerScene>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    if let Ok(player_transform) = player_query.single() {
+        let player_position = player_transform.translation.truncate();
+        if enemies
+            .iter()
+            .any(|enemy| enemy.translation.truncate().distance(player_position) < TILE_SIZE * 0.6)
+        {
+            let window = windows.get_primary().expect("No primary window");
+            let start = current_map.start_position();
+            trigger_scene.send(TriggerScene {
+                scene: CutScene::Caught {
+                    player_from: (player_position.x, player_position.y),
+                    camera_from: calc_camera_position(
+                        player_position.x,
+                        player_position.y,
+                        window,
+                        &current_map.dimensions(),
+                    ),
+                    camera_to: calc_camera_position(
+                        start.0,
+                        start.1,
+                        window,
+                        &current_map.dimensions(),
+                    ),
+                },
+            });
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use crate::audio::AudioEffect;
+use crate::dynamic_tiles::{carries, Platform};
+use crate::lava::RisingLavaTile;
+use crate::loading::AudioAssets;
+use crate::map::{Map, MapSystemLabels, MapTile, Slot, TILE_SIZE};
+use crate::player::{calc_camera_position, Player, PlayerSystemLabels};
+use crate::scenes::{CutScene, TriggerScene};
+use crate::wiring::Switch;
+use crate::{GameData, GameState};
+use bevy::prelude::*;
+
+pub const HAZARD_TINT: Color = Color::rgb(1., 0.35, 0.1);
+pub const CHECKPOINT_TINT: Color = Color::rgb(0.5, 1., 0.6);
+const REACHED_CHECKPOINT_TINT: Color = Color::rgb(0.8, 1., 0.3);
+
+pub struct HazardsPlugin;
+
+impl Plugin for HazardsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Respawn>().add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .after(MapSystemLabels::DrawMap)
+                .with_system(reset_respawn.system())
+                .with_system(
+                    reach_checkpoints
+                        .system()
+                        .after(PlayerSystemLabels::MovePlayer),
+                )
+                .with_system(touch_hazards.system().after(PlayerSystemLabels::MovePlayer)),
+        );
+    }
+}
+
+pub struct Hazard;
+
+pub struct Checkpoint;
+
+// Where the squirrel comes back after dying and the switches at that moment
+#[derive(Default)]
+pub struct Respawn {
+    pub slot: Option<Slot>,
+    pub position: (f32, f32),
+    pub signals: Vec<bool>,
+}
+
+fn reset_respawn(current_map: Res<Map>, mut respawn: ResMut<Respawn>) {
+    if !current_map.is_changed() {
+        return;
+    }
+    respawn.slot = None;
+    respawn.position = current_map.start_position();
+    respawn.signals = vec![false; current_map.active_elements().len()];
+}
+
+fn reach_checkpoints(
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    asset_server: Res<AssetServer>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut respawn: ResMut<Respawn>,
+    mut checkpoints: Query<(&MapTile, &mut Handle<ColorMaterial>), With<Checkpoint>>,
+    switches: Query<&Switch>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    let player_transform = match player_query.single() {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+    let player_slot = current_map.slot_from_position(
+        player_transform.translation.x,
+        player_transform.translation.y,
+    );
+    if respawn.slot.as_ref() == Some(&player_slot) {
+        return;
+    }
+    let reached = checkpoints
+        .iter_mut()
+        .any(|(tile, _)| tile.column == player_slot.column && tile.row == player_slot.row);
+    if !reached {
+        return;
+    }
+    for (tile, mut material) in checkpoints.iter_mut() {
+        let tint = if tile.column == player_slot.column && tile.row == player_slot.row {
+            REACHED_CHECKPOINT_TINT
+        } else {
+            CHECKPOINT_TINT
+        };
+        if let Some(path) = &tile.tile.asset_path {
+            *material = materials.add(ColorMaterial::modulated_texture(
+                asset_server.get_handle(&(path)[3..]),
+                tint,
+            ));
+        }
+    }
+    let mut signals = vec![false; current_map.active_elements().len()];
+    for switch in switches.iter() {
+        if let Some(signal) = signals.get_mut(switch.index) {
+            *signal = switch.lasting_signal();
+        }
+    }
+    respawn.position = (
+        player_slot.column as f32 * TILE_SIZE,
+        player_slot.row as f32 * TILE_SIZE,
+    );
+    respawn.slot = Some(player_slot);
+    respawn.signals = signals;
+    audio_effect.send(AudioEffect {
+        handle: audio_assets.button_click.clone(),
+    });
+}
+
+fn touch_hazards(
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    windows: Res<Windows>,
+    respawn: Res<Respawn>,
+    mut trigger_scene: EventWriter<TriggerScene>,
+    hazards: Query<(&MapTile, Option<&RisingLavaTile>), With<Hazard>>,
+    platforms: Query<&Transform, With<Platform>>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    let player_transform = match player_query.single() {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+    let player_position = player_transform.translation.truncate();
+    if platforms
+        .iter()
+        .any(|platform| carries(platform, player_position))
+    {
+        return;
+    }
+    let player_slot = current_map.slot_from_position(player_position.x, player_position.y);
+    let on_hazard = |slot: &Slot| {
+        hazards
+            .iter()
+            .find(|(tile, _)| tile.column == slot.column && tile.row == slot.row)
+    };
+    let rising_lava = match on_hazard(&player_slot) {
+        Some((_, rising_lava)) => rising_lava.is_some(),
+        None => return,
+    };
+    // start over if the lava got the squirrel or already covers the checkpoint
+    let restart_level = rising_lava
+        || respawn
+            .slot
+            .as_ref()
+            .map_or(false, |slot| on_hazard(slot).is_some());
+    let respawn_position = if restart_level {
+        current_map.start_position()
+    } else {
+        respawn.position
+    };
+    let window = windows.get_primary().expect("No primary window");
+    trigger_scene.send(TriggerScene {
+        scene: CutScene::Died {
+            player_from: (player_position.x, player_position.y),
+            respawn: respawn_position,
+            camera_from: calc_camera_position(
+                player_position.x,
+                player_position.y,
+                window,
+                &current_map.dimensions(),
+            ),
+            camera_to: calc_camera_position(
+                respawn_position.0,
+                respawn_position.1,
+                window,
+                &current_map.dimensions(),
+            ),
+            restart_level,
+            signals: if current_map.keeps_switches_on_death() {
+                None
+            } else {
+                Some(respawn.signals.clone())
+            },
+        },
+    });
+}
@@ -0,0 +1,150 @@
+use crate::actions::Actions;
+use crate::audio::AudioEffect;
+use crate::loading::AudioAssets;
+use crate::map::{Collide, LevelEntity, Map, MapSystemLabels, Slot, ACTIVE_ELEMENT_Z, TILE_SIZE};
+use crate::pathfinding::Grid;
+use crate::player::Player;
+use crate::tween::{Easing, Tween, TweenCompleted, TweenProperty};
+use crate::{GameData, GameState, TiledMap};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+
+const HINT_DURATION: Duration = Duration::from_secs(2);
+// only the next few steps are shown
+const HINT_LENGTH: usize = 12;
+const HINT_MARKER_SIZE: f32 = 14.;
+const HINT_COLOR: Color = Color::rgba(1., 0.9, 0.4, 0.8);
+const HINT_FADED: u32 = 1;
+
+pub struct HintsPlugin;
+
+impl Plugin for HintsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Hints>().add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .after(MapSystemLabels::DrawMap)
+                .with_system(reset_hints.system())
+                .with_system(show_hint.system())
+                .with_system(fade_hint_markers.system()),
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct Hints {
+    pub used: usize,
+}
+
+struct HintMarker;
+
+fn reset_hints(
+    mut commands: Commands,
+    current_map: Res<Map>,
+    mut hints: ResMut<Hints>,
+    markers: Query<Entity, With<HintMarker>>,
+) {
+    if !current_map.is_changed() {
+        return;
+    }
+    hints.used = 0;
+    for entity in markers.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn show_hint(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    maps: Res<Assets<TiledMap>>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut hints: ResMut<Hints>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    colliders: Query<&Collide>,
+    markers: Query<Entity, With<HintMarker>>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if !actions.hint || game_state.frozen {
+        return;
+    }
+    let player_transform = match player_query.single() {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+    let player_slot = current_map.slot_from_position(
+        player_transform.translation.x,
+        player_transform.translation.y,
+    );
+    let grid = Grid::new(&current_map.dimensions(), colliders.iter());
+    let distances = grid.distances(&player_slot);
+    let exit = current_map
+        .exits(&maps)
+        .into_iter()
+        .map(|exit| current_map.tiled_slot_to_bevy_slot(exit.position))
+        .filter(|slot| distances.contains_key(slot))
+        .min_by_key(|slot| distances[slot]);
+    let exit = match exit {
+        Some(exit) => exit,
+        None => {
+            audio_effect.send(AudioEffect {
+                handle: audio_assets.no_no.clone(),
+            });
+            return;
+        }
+    };
+    for entity in markers.iter() {
+        commands.entity(entity).despawn();
+    }
+    let mut path: Vec<Slot> = vec![];
+    let mut slot = player_slot;
+    while path.len() < HINT_LENGTH {
+        match grid.next_step(&slot, &exit) {
+            Some(next) => {
+                path.push(next.clone());
+                slot = next;
+            }
+            None => break,
+        }
+    }
+    for slot in path {
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(HINT_COLOR.into()),
+                sprite: Sprite::new(Vec2::new(HINT_MARKER_SIZE, HINT_MARKER_SIZE)),
+                transform: Transform::from_translation(Vec3::new(
+                    slot.column as f32 * TILE_SIZE,
+                    slot.row as f32 * TILE_SIZE,
+                    ACTIVE_ELEMENT_Z,
+                )),
+                ..Default::default()
+            })
+            .insert(HintMarker)
+            .insert(LevelEntity)
+            .insert(
+                Tween::new(HINT_DURATION, Easing::QuadIn)
+                    .with(TweenProperty::Color {
+                        from: HINT_COLOR,
+                        to: Color::rgba(1., 0.9, 0.4, 0.),
+                    })
+                    .tagged(HINT_FADED),
+            );
+    }
+    hints.used += 1;
+    audio_effect.send(AudioEffect {
+        handle: audio_assets.tick.clone(),
+    });
+}
+
+fn fade_hint_markers(
+    mut commands: Commands,
+    mut completed: EventReader<TweenCompleted>,
+    markers: Query<Entity, With<HintMarker>>,
+) {
+    for tween in completed.iter() {
+        if tween.tag == HINT_FADED && markers.get(tween.entity).is_ok() {
+            commands.entity(tween.entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use crate::audio::AudioEffect;
+use crate::loading::{AudioAssets, TextureAssets};
+use crate::map::{
+    is_touching, Collide, LevelEntity, Map, MapSystemLabels, Slot, ACTIVE_ELEMENT_Z, TILE_SIZE,
+};
+use crate::player::Player;
+use crate::{GameData, GameState, TiledMap};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<RestoreInventory>().add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .after(MapSystemLabels::DrawMap)
+                .with_system(draw_keys_and_doors.system())
+                .with_system(reset_inventory.system())
+                .with_system(pick_up_keys.system())
+                .with_system(unlock_doors.system())
+                .with_system(restore_inventory.system()),
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum KeyColor {
+    Red,
+    Blue,
+}
+
+impl KeyColor {
+    pub fn color(&self) -> Color {
+        match self {
+            KeyColor::Red => Color::rgb(0.9, 0.2, 0.2),
+            KeyColor::Blue => Color::rgb(0.3, 0.5, 1.),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Item {
+    Key(KeyColor),
+}
+
+impl Item {
+    pub fn color(&self) -> Color {
+        match self {
+            Item::Key(key_color) => key_color.color(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Inventory {
+    pub items: Vec<Item>,
+}
+
+#[derive(Clone)]
+pub struct KeyPlacement {
+    pub position: Slot,
+    pub color: KeyColor,
+}
+
+#[derive(Clone)]
+pub struct DoorPlacement {
+    pub position: Slot,
+    pub color: KeyColor,
+}
+
+pub struct Key {
+    color: KeyColor,
+    pub slot: Slot,
+}
+
+pub struct LockedDoor {
+    color: KeyColor,
+}
+
+struct InventoryTile;
+
+// Sent when loading a save; slots are bevy slots
+pub struct RestoreInventory {
+    pub items: Vec<Item>,
+    pub picked_keys: Vec<Slot>,
+    pub unlocked_doors: Vec<Slot>,
+}
+
+const KEY_SIZE: f32 = 24.;
+
+fn draw_keys_and_doors(
+    mut commands: Commands,
+    current_map: Res<Map>,
+    maps: Res<Assets<TiledMap>>,
+    textures: Res<TextureAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    tiles: Query<Entity, With<InventoryTile>>,
+) {
+    if !current_map.is_added() && !current_map.is_changed() {
+        return;
+    }
+    for entity in tiles.iter() {
+        commands.entity(entity).despawn();
+    }
+    for key in current_map.keys(&maps) {
+        let slot = current_map.tiled_slot_to_bevy_slot(key.position);
+        let mut transform = Transform::from_translation(Vec3::new(
+            slot.column as f32 * TILE_SIZE,
+            slot.row as f32 * TILE_SIZE,
+            ACTIVE_ELEMENT_Z,
+        ));
+        transform.rotation = Quat::from_rotation_z(PI / 4.);
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(key.color.color().into()),
+                sprite: Sprite::new(Vec2::new(KEY_SIZE, KEY_SIZE)),
+                transform,
+                ..Default::default()
+            })
+            .insert(Key {
+                color: key.color,
+                slot,
+            })
+            .insert(InventoryTile)
+            .insert(LevelEntity);
+    }
+    for door in current_map.locked_doors(&maps) {
+        let slot = current_map.tiled_slot_to_bevy_slot(door.position);
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(ColorMaterial::modulated_texture(
+                    textures.texture_wall_up.clone(),
+                    door.color.color(),
+                )),
+                transform: Transform::from_translation(Vec3::new(
+                    slot.column as f32 * TILE_SIZE,
+                    slot.row as f32 * TILE_SIZE,
+                    ACTIVE_ELEMENT_Z,
+                )),
+                ..Default::default()
+            })
+            .insert(Collide {
+                x: slot.column,
+                y: slot.row,
+            })
+            .insert(LockedDoor { color: door.color })
+            .insert(InventoryTile)
+            .insert(LevelEntity);
+    }
+}
+
+fn reset_inventory(current_map: Res<Map>, mut inventory: Query<&mut Inventory, With<Player>>) {
+    if !current_map.is_changed() {
+        return;
+    }
+    if let Ok(mut inventory) = inventory.single_mut() {
+        inventory.items.clear();
+    }
+}
+
+fn pick_up_keys(
+    mut commands: Commands,
+    game_state: Res<GameData>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    keys: Query<(Entity, &Transform, &Key)>,
+    mut player_query: Query<(&Transform, &mut Inventory), With<Player>>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    if let Ok((player_transform, mut inventory)) = player_query.single_mut() {
+        for (entity, transform, key) in keys.iter() {
+            if is_touching(player_transform, transform) {
+                inventory.items.push(Item::Key(key.color));
+                commands.entity(entity).despawn();
+                audio_effect.send(AudioEffect {
+                    handle: audio_assets.button_click.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn unlock_doors(
+    mut commands: Commands,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    doors: Query<(Entity, &Collide, &LockedDoor)>,
+    mut player_query: Query<(&Transform, &mut Inventory), With<Player>>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    if let Ok((player_transform, mut inventory)) = player_query.single_mut() {
+        let player_slot = current_map.slot_from_position(
+            player_transform.translation.x,
+            player_transform.translation.y,
+        );
+        for (entity, collide, door) in doors.iter() {
+            // free movement is blocked right before the door, so both control schemes end up next to it
+            let door_slot = Slot {
+                column: collide.x,
+                row: collide.y,
+            };
+            if !is_adjacent(&player_slot, &door_slot) {
+                continue;
+            }
+            if let Some(index) = inventory
+                .items
+                .iter()
+                .position(|item| item == &Item::Key(door.color))
+            {
+                inventory.items.remove(index);
+                commands.entity(entity).despawn();
+                audio_effect.send(AudioEffect {
+                    handle: audio_assets.wall_moving.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn is_adjacent(slot: &Slot, other: &Slot) -> bool {
+    let columns = (slot.column as i64 - other.column as i64).abs();
+    let rows = (slot.row as i64 - other.row as i64).abs();
+    columns + rows == 1
+}
+
+fn restore_inventory(
+    mut commands: Commands,
+    mut restore_events: EventReader<RestoreInventory>,
+    keys: Query<(Entity, &Key)>,
+    doors: Query<(Entity, &Collide), With<LockedDoor>>,
+    mut inventory: Query<&mut Inventory, With<Player>>,
+) {
+    for restore in restore_events.iter() {
+        if let Ok(mut inventory) = inventory.single_mut() {
+            inventory.items = restore.items.clone();
+        }
+        for (entity, key) in keys.iter() {
+            if restore.picked_keys.contains(&key.slot) {
+                commands.entity(entity).despawn();
+            }
+        }
+        for (entity, collide) in doors.iter() {
+            let slot = Slot {
+                column: collide.x,
+                row: collide.y,
+            };
+            if restore.unlocked_doors.contains(&slot) {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use crate::audio::BackgroundAudio;
+use crate::hazards::{Hazard, HAZARD_TINT};
+use crate::loading::AudioAssets;
+use crate::map::{Collide, LevelEntity, Map, MapSystemLabels, MapTile, Slot, Tile, TILE_SIZE};
+use crate::pathfinding::Grid;
+use crate::scenes::CutScene;
+use crate::{GameData, GameState};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use std::collections::HashSet;
+
+const LAVA_Z: f32 = 0.2;
+const LAVA_TEXTURE: &str = "textures/lavafloor.png";
+const FRONT_TINT: Color = Color::rgb(1., 0.9, 0.3);
+
+pub struct LavaPlugin;
+
+impl Plugin for LavaPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<LavaFlow>()
+            .add_event::<RestoreLava>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .after(MapSystemLabels::DrawMap)
+                    .with_system(reset_lava.system())
+                    .with_system(start_lava.system())
+                    .with_system(spread_lava.system())
+                    .with_system(animate_lava_front.system())
+                    .with_system(restore_lava.system()),
+            );
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+#[derive(Clone)]
+pub struct RisingLava {
+    pub edge: Edge,
+    pub interval: Duration,
+}
+
+#[derive(PartialEq)]
+enum LavaState {
+    Dormant,
+    WaitingForIntro { intro_seen: bool },
+    Rising,
+}
+
+pub struct LavaFlow {
+    state: LavaState,
+    timer: Timer,
+    edge: Edge,
+    flooded: HashSet<Slot>,
+}
+
+impl Default for LavaFlow {
+    fn default() -> Self {
+        LavaFlow {
+            state: LavaState::Dormant,
+            timer: Timer::new(Duration::from_secs(1), true),
+            edge: Edge::Top,
+            flooded: HashSet::default(),
+        }
+    }
+}
+
+impl LavaFlow {
+    pub fn flooded(&self) -> Vec<Slot> {
+        self.flooded.iter().cloned().collect()
+    }
+}
+
+// Sent when loading a save
+pub struct RestoreLava {
+    pub flooded: Vec<Slot>,
+}
+
+pub struct RisingLavaTile;
+
+struct LavaFront {
+    timer: Timer,
+}
+
+fn reset_lava(current_map: Res<Map>, mut flow: ResMut<LavaFlow>) {
+    if !current_map.is_changed() {
+        return;
+    }
+    flow.flooded.clear();
+    match current_map.rising_lava() {
+        Some(rising) => {
+            flow.state = LavaState::WaitingForIntro { intro_seen: false };
+            flow.timer = Timer::new(rising.interval, true);
+            flow.edge = rising.edge;
+        }
+        None => flow.state = LavaState::Dormant,
+    }
+}
+
+fn start_lava(
+    game_state: Res<GameData>,
+    audio_assets: Res<AudioAssets>,
+    mut background_audio: EventWriter<BackgroundAudio>,
+    mut flow: ResMut<LavaFlow>,
+) {
+    if let LavaState::WaitingForIntro { intro_seen } = flow.state {
+        if let Some(CutScene::Intro { .. }) = game_state.scene {
+            flow.state = LavaState::WaitingForIntro { intro_seen: true };
+        } else if intro_seen && game_state.scene.is_none() {
+            flow.state = LavaState::Rising;
+            background_audio.send(BackgroundAudio {
+                handles: vec![
+                    audio_assets.lava_background.clone(),
+                    audio_assets.lava_background_effects.clone(),
+                ],
+            });
+        }
+    }
+}
+
+fn spread_lava(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut flow: ResMut<LavaFlow>,
+    colliders: Query<&Collide>,
+) {
+    if flow.state != LavaState::Rising || game_state.frozen {
+        return;
+    }
+    flow.timer.tick(time.delta());
+    if !flow.timer.just_finished() {
+        return;
+    }
+    let dimensions = current_map.dimensions();
+    let grid = Grid::new(&dimensions, colliders.iter());
+    let next: HashSet<Slot> = if flow.flooded.is_empty() {
+        edge_slots(&grid, flow.edge, dimensions.columns, dimensions.rows)
+    } else {
+        flow.flooded
+            .iter()
+            .flat_map(|slot| grid.neighbours(slot))
+            .filter(|slot| !flow.flooded.contains(slot))
+            .collect()
+    };
+    for slot in next {
+        spawn_lava(
+            &mut commands,
+            &asset_server,
+            &mut materials,
+            &slot,
+            flow.timer.duration(),
+        );
+        flow.flooded.insert(slot);
+    }
+}
+
+fn spawn_lava(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    materials: &mut Assets<ColorMaterial>,
+    slot: &Slot,
+    rise: Duration,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            material: materials.add(ColorMaterial::modulated_texture(
+                asset_server.get_handle(LAVA_TEXTURE),
+                FRONT_TINT,
+            )),
+            transform: Transform {
+                translation: Vec3::new(
+                    slot.column as f32 * TILE_SIZE,
+                    slot.row as f32 * TILE_SIZE,
+                    LAVA_Z,
+                ),
+                scale: Vec3::ZERO,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(MapTile {
+            column: slot.column,
+            row: slot.row,
+            tile: Tile {
+                asset_path: Some(format!("../{}", LAVA_TEXTURE)),
+                diggable: false,
+            },
+        })
+        .insert(Hazard)
+        .insert(RisingLavaTile)
+        .insert(LevelEntity)
+        .insert(LavaFront {
+            timer: Timer::new(rise, false),
+        });
+}
+
+fn restore_lava(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut restore_events: EventReader<RestoreLava>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut flow: ResMut<LavaFlow>,
+) {
+    for restore in restore_events.iter() {
+        let rise = flow.timer.duration();
+        for slot in restore.flooded.iter() {
+            if flow.flooded.insert(slot.clone()) {
+                spawn_lava(&mut commands, &asset_server, &mut materials, slot, rise);
+            }
+        }
+    }
+}
+
+// The free slots in the outermost row or column on the given edge
+fn edge_slots(grid: &Grid, edge: Edge, columns: usize, rows: usize) -> HashSet<Slot> {
+    let lines: Vec<Vec<Slot>> = match edge {
+        Edge::Top => (0..rows)
+            .rev()
+            .map(|row| (0..columns).map(|column| Slot { column, row }).collect())
+            .collect(),
+        Edge::Bottom => (0..rows)
+            .map(|row| (0..columns).map(|column| Slot { column, row }).collect())
+            .collect(),
+        Edge::Left => (0..columns)
+            .map(|column| (0..rows).map(|row| Slot { column, row }).collect())
+            .collect(),
+        Edge::Right => (0..columns)
+            .rev()
+            .map(|column| (0..rows).map(|row| Slot { column, row }).collect())
+            .collect(),
+    };
+    lines
+        .into_iter()
+        .map(|line| {
+            line.into_iter()
+                .filter(|slot| grid.is_free(slot))
+                .collect::<HashSet<Slot>>()
+        })
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+}
+
+fn animate_lava_front(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_state: Res<GameData>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut front: Query<(
+        Entity,
+        &mut LavaFront,
+        &mut Transform,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    for (entity, mut lava_front, mut transform, material) in front.iter_mut() {
+        lava_front.timer.tick(time.delta());
+        let progress = lava_front.timer.percent();
+        transform.scale = Vec3::splat((progress * 2.).min(1.));
+        if let Some(material) = materials.get_mut(material) {
+            material.color = Color::rgb(
+                FRONT_TINT.r() + (HAZARD_TINT.r() - FRONT_TINT.r()) * progress,
+                FRONT_TINT.g() + (HAZARD_TINT.g() - FRONT_TINT.g()) * progress,
+                FRONT_TINT.b() + (HAZARD_TINT.b() - FRONT_TINT.b()) * progress,
+            );
+        }
+        if lava_front.timer.finished() {
+            commands.entity(entity).remove::<LavaFront>();
+        }
+    }
+}
@@ -1,23 +1,66 @@
+mod acorn;
 mod actions;
 mod audio;
+mod blocks;
+mod dash;
+mod digging;
+mod dynamic_tiles;
+mod enemies;
+mod hazards;
+mod hints;
+mod inventory;
+mod lava;
 mod loading;
 mod map;
 mod menu;
+mod pathfinding;
+mod pause;
 mod player;
+mod rewind;
+mod routes;
+mod save;
 mod scenes;
+mod score;
+mod settings;
+mod skip;
+mod storage;
+mod timeline;
+mod tween;
 mod ui;
+mod wiring;
 
+use crate::acorn::AcornPlugin;
 use crate::actions::ActionsPlugin;
 use crate::audio::InternalAudioPlugin;
+use crate::blocks::BlocksPlugin;
+use crate::dash::DashPlugin;
+use crate::digging::DiggingPlugin;
+use crate::dynamic_tiles::DynamicTilesPlugin;
+use crate::enemies::EnemiesPlugin;
+use crate::hazards::HazardsPlugin;
+use crate::hints::HintsPlugin;
+use crate::inventory::InventoryPlugin;
+use crate::lava::LavaPlugin;
 use crate::loading::LoadingPlugin;
 use crate::menu::MenuPlugin;
+use crate::pause::PausePlugin;
 use crate::player::PlayerPlugin;
+use crate::settings::SettingsPlugin;
+use crate::skip::SkipPlugin;
+use crate::storage::GameStorage;
+use crate::timeline::TimelinePlugin;
+use crate::tween::TweenPlugin;
 
 use bevy::app::AppBuilder;
 // use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use crate::map::MapPlugin;
+use crate::rewind::RewindPlugin;
+use crate::routes::RoutesPlugin;
+use crate::save::SavePlugin;
 use crate::scenes::{CutScene, ScenesPlugin};
+use crate::score::ScorePlugin;
 use crate::ui::UiPlugin;
+use crate::wiring::WiringPlugin;
 use anyhow::Result;
 use bevy::asset::{AssetLoader, AssetServerSettings, BoxedFuture, LoadContext, LoadedAsset};
 use bevy::prelude::*;
@@ -31,6 +74,8 @@ use tiled::Map;
 enum GameState {
     Loading,
     Playing,
+    // pushed on top of Playing, which keeps the level around
+    Paused,
     Menu,
 }
 
@@ -49,14 +94,35 @@ impl Plugin for GamePlugin {
             .add_asset_loader(TiledMapLoader::new(asset_folder))
             .add_state(GameState::Loading)
             .init_resource::<GameData>()
+            .init_resource::<GameStorage>()
             .add_plugin(LoadingPlugin)
+            .add_plugin(TimelinePlugin)
+            .add_plugin(TweenPlugin)
+            .add_plugin(SettingsPlugin)
             .add_plugin(ActionsPlugin)
+            .add_plugin(SkipPlugin)
             .add_plugin(ScenesPlugin)
             .add_plugin(MenuPlugin)
             .add_plugin(InternalAudioPlugin)
             .add_plugin(PlayerPlugin)
             .add_plugin(MapPlugin)
             .add_plugin(UiPlugin)
+            .add_plugin(WiringPlugin)
+            .add_plugin(InventoryPlugin)
+            .add_plugin(BlocksPlugin)
+            .add_plugin(RoutesPlugin)
+            .add_plugin(EnemiesPlugin)
+            .add_plugin(AcornPlugin)
+            .add_plugin(HazardsPlugin)
+            .add_plugin(LavaPlugin)
+            .add_plugin(DynamicTilesPlugin)
+            .add_plugin(DiggingPlugin)
+            .add_plugin(DashPlugin)
+            .add_plugin(HintsPlugin)
+            .add_plugin(ScorePlugin)
+            .add_plugin(RewindPlugin)
+            .add_plugin(SavePlugin)
+            .add_plugin(PausePlugin)
             // .add_plugin(FrameTimeDiagnosticsPlugin::default())
             // .add_plugin(LogDiagnosticsPlugin::default())
             ;
@@ -66,8 +132,10 @@ impl Plugin for GamePlugin {
 pub struct GameData {
     pub frozen: bool,
     pub won: bool,
+    pub fleeing_acorn: bool,
     pub scene: Option<CutScene>,
-    pub scene_start: Duration,
+    // how long the active scene has been running; stands still while paused
+    pub scene_time: Duration,
     pub scene_step: u16,
 }
 
@@ -75,9 +143,10 @@ impl Default for GameData {
     fn default() -> Self {
         Self {
             won: false,
+            fleeing_acorn: false,
             frozen: false,
             scene: None,
-            scene_start: Duration::from_nanos(0),
+            scene_time: Duration::from_nanos(0),
             scene_step: 0,
         }
     }
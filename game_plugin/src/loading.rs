@@ -22,6 +22,7 @@ pub struct LoadingState {
     fonts: Vec<HandleUntyped>,
     audio: Vec<HandleUntyped>,
     maps: Vec<HandleUntyped>,
+    timelines: Vec<HandleUntyped>,
 }
 
 pub struct FontAssets {
@@ -33,6 +34,7 @@ pub struct AudioAssets {
     pub won: Handle<AudioSource>,
     pub button_click: Handle<AudioSource>,
     pub wall_moving: Handle<AudioSource>,
+    pub tick: Handle<AudioSource>,
     pub no_no: Handle<AudioSource>,
     pub puh: Handle<AudioSource>,
     pub ground_background: Handle<AudioSource>,
@@ -64,6 +66,7 @@ fn start_loading(mut commands: Commands, asset_server: Res<AssetServer>) {
     audio.push(asset_server.load_untyped(PATHS.audio_puh));
     audio.push(asset_server.load_untyped(PATHS.audio_button_click));
     audio.push(asset_server.load_untyped(PATHS.audio_wall_moving));
+    audio.push(asset_server.load_untyped(PATHS.audio_tick));
     audio.push(asset_server.load_untyped(PATHS.audio_ground_background));
     audio.push(asset_server.load_untyped(PATHS.audio_ground_background_effects));
     audio.push(asset_server.load_untyped(PATHS.audio_dirt_background));
@@ -102,11 +105,24 @@ fn start_loading(mut commands: Commands, asset_server: Res<AssetServer>) {
     maps.push(asset_server.load_untyped("map/stone.tmx"));
     maps.push(asset_server.load_untyped("map/lava.tmx"));
 
+    let timeline_names = [
+        "intro",
+        "intro_lava",
+        "activate_button",
+        "map_transition",
+        "won",
+    ];
+    let mut timelines: Vec<HandleUntyped> = vec![];
+    for name in &timeline_names {
+        timelines.push(asset_server.load_untyped(&format!("cutscenes/{}.timeline", name)[..]));
+    }
+
     commands.insert_resource(LoadingState {
         textures,
         fonts,
         audio,
         maps,
+        timelines,
     });
 }
 
@@ -136,6 +152,11 @@ fn check_state(
     {
         return;
     }
+    if LoadState::Loaded
+        != asset_server.get_group_load_state(loading_state.timelines.iter().map(|handle| handle.id))
+    {
+        return;
+    }
 
     commands.insert_resource(FontAssets {
         fira_sans: asset_server.get_handle(PATHS.fira_sans),
@@ -148,6 +169,7 @@ fn check_state(
         puh: asset_server.get_handle(PATHS.audio_puh),
         button_click: asset_server.get_handle(PATHS.audio_button_click),
         wall_moving: asset_server.get_handle(PATHS.audio_wall_moving),
+        tick: asset_server.get_handle(PATHS.audio_tick),
         ground_background: asset_server.get_handle(PATHS.audio_ground_background),
         ground_background_effects: asset_server.get_handle(PATHS.audio_ground_background_effects),
         dirt_background: asset_server.get_handle(PATHS.audio_dirt_background),
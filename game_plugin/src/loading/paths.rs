@@ -6,6 +6,7 @@ pub struct AssetPaths {
     pub audio_puh: &'static str,
     pub audio_button_click: &'static str,
     pub audio_wall_moving: &'static str,
+    pub audio_tick: &'static str,
     pub audio_ground_background: &'static str,
     pub audio_ground_background_effects: &'static str,
     pub audio_dirt_background: &'static str,
@@ -29,6 +30,7 @@ pub const PATHS: AssetPaths = AssetPaths {
     audio_puh: "audio/puh.ogg",
     audio_button_click: "audio/button_click.ogg",
     audio_wall_moving: "audio/wall_moving.ogg",
+    audio_tick: "audio/buttonclick.ogg",
     audio_ground_background: "audio/happy_background.ogg",
     audio_ground_background_effects: "audio/birds.ogg",
     audio_dirt_background: "audio/dirt_background.ogg",
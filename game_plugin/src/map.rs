@@ -1,11 +1,25 @@
+use crate::dynamic_tiles::{CrumblingTile, Platform, CRUMBLING_TINT, PLATFORM_TINT, PLATFORM_Z};
+use crate::enemies::EnemySpawn;
+use crate::hazards::{Checkpoint, Hazard, CHECKPOINT_TINT, HAZARD_TINT};
+use crate::inventory::{DoorPlacement, KeyPlacement};
+use crate::lava::{Edge, RisingLava};
 use crate::loading::TextureAssets;
-use crate::player::{calc_camera_position, Player};
+use crate::player::{calc_camera_position, SlowTile, SLOW_TILE_TINT};
 use crate::scenes::{CutScene, TriggerScene};
-use crate::{GameData, GameState, TiledMap};
+use crate::wiring::{
+    switch_material, Countdown, Gate, Lever, PressurePlate, Switch, SwitchKind, TimedButton,
+    Trigger, Wall,
+};
+use crate::{GameState, TiledMap};
 use bevy::prelude::*;
-use std::collections::HashMap;
+use bevy::render::camera::Camera;
+use bevy::utils::Duration;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use tiled::LayerData::Finite;
-use tiled::PropertyValue::BoolValue;
+use tiled::ObjectShape;
+use tiled::PropertyValue::{BoolValue, StringValue};
 
 pub const TILE_SIZE: f32 = 64.;
 pub const ACTIVE_ELEMENT_Z: f32 = 2.;
@@ -29,8 +43,10 @@ impl Plugin for MapPlugin {
             .add_system_set(
                 SystemSet::on_update(GameState::Playing)
                     .with_system(draw_active_elements.system())
-                    .with_system(check_active_elements.system())
                     .after(MapSystemLabels::DrawMap),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(remove_level.system()),
             );
     }
 }
@@ -38,11 +54,16 @@ impl Plugin for MapPlugin {
 pub struct MapData {
     layers: Vec<Vec<Vec<Tile>>>,
     colliding_layers: Vec<bool>,
+    hazard_layers: Vec<bool>,
+    checkpoint_layers: Vec<bool>,
+    crumbling_layers: Vec<bool>,
+    slow_layers: Vec<bool>,
+    platform_paths: Vec<Vec<Vec2>>,
     height: usize,
     width: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Slot {
     pub column: usize,
     pub row: usize,
@@ -56,6 +77,7 @@ pub struct Dimensions {
 #[derive(Debug, PartialEq, Clone)]
 pub struct Tile {
     pub asset_path: Option<String>,
+    pub diggable: bool,
 }
 
 pub struct MapTile {
@@ -71,7 +93,10 @@ pub struct Collide {
 
 pub struct Acorn;
 
-#[derive(Clone, PartialEq)]
+// Everything spawned for the running level, removed when the game is left
+pub struct LevelEntity;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Map {
     Ground,
     Dirt,
@@ -84,7 +109,7 @@ impl Map {
         format!("map/{}.tmx", self.name())
     }
 
-    fn name(&self) -> &str {
+    pub fn name(&self) -> &str {
         match self {
             Map::Ground => "ground",
             Map::Dirt => "dirt",
@@ -150,6 +175,38 @@ impl Map {
         }
     }
 
+    pub fn rising_lava(&self) -> Option<RisingLava> {
+        match self {
+            Map::Lava => Some(RisingLava {
+                edge: Edge::Top,
+                interval: Duration::from_millis(2500),
+            }),
+            _ => None,
+        }
+    }
+
+    // How many soft walls the squirrel may dig through
+    pub fn digs(&self) -> usize {
+        match self {
+            Map::Dirt => 3,
+            _ => 0,
+        }
+    }
+
+    // How many seconds can be rewound in total
+    pub fn rewind_budget(&self) -> Duration {
+        match self {
+            Map::Ground => Duration::from_secs(3),
+            Map::Dirt | Map::Stone => Duration::from_secs(5),
+            Map::Lava => Duration::from_secs(8),
+        }
+    }
+
+    // Whether buttons and walls keep their state when the squirrel dies
+    pub fn keeps_switches_on_death(&self) -> bool {
+        !matches!(self, Map::Lava)
+    }
+
     pub fn tiled_slot_to_bevy_slot(&self, slot: Slot) -> Slot {
         Slot {
             column: slot.column,
@@ -157,6 +214,13 @@ impl Map {
         }
     }
 
+    pub fn slot_from_position(&self, x: f32, y: f32) -> Slot {
+        Slot {
+            column: ((x + TILE_SIZE / 2.) / TILE_SIZE) as usize,
+            row: ((y + TILE_SIZE / 2.) / TILE_SIZE) as usize,
+        }
+    }
+
     pub fn position_from_slot(&self, slot: Slot) -> (f32, f32) {
         let dimensions = self.dimensions();
         (
@@ -192,24 +256,39 @@ impl Map {
         }
     }
 
-    pub fn goal_scene(&self, from: (f32, f32)) -> Option<CutScene> {
-        match self {
-            Map::Ground => Some(CutScene::MapTransition {
-                to: Map::Dirt,
-                camera_to: self.goal_position(),
-                camera_from: from,
-            }),
-            Map::Dirt => Some(CutScene::MapTransition {
-                to: Map::Stone,
-                camera_to: self.goal_position(),
+    // Objects in the "exits" layer; an exit without a "to" property like `Dirt` leaves the game
+    pub fn exits(&self, maps: &Assets<TiledMap>) -> Vec<Exit> {
+        self.objects(maps, "exits")
+            .iter()
+            .filter_map(|object| {
+                let to = match object.properties.get("to") {
+                    Some(_) => Some(self.ron_property(object, "to")?),
+                    None => None,
+                };
+                Some(Exit {
+                    position: tiled_slot_at(object.x, object.y),
+                    to,
+                })
+            })
+            .collect()
+    }
+
+    pub fn exit_scene(
+        &self,
+        exit: &Exit,
+        from: (f32, f32),
+        acorn_caught: Option<(f32, f32)>,
+    ) -> Option<CutScene> {
+        match &exit.to {
+            Some(to) => Some(CutScene::MapTransition {
+                to: to.clone(),
+                camera_to: self.position_from_slot(exit.position.clone()),
                 camera_from: from,
             }),
-            Map::Stone => Some(CutScene::MapTransition {
-                to: Map::Lava,
-                camera_to: self.goal_position(),
-                camera_from: from,
+            // a caught acorn is gone by now, otherwise it went down the hole at the goal
+            None => Some(CutScene::Won {
+                acorn: acorn_caught.unwrap_or_else(|| self.goal_position()),
             }),
-            Map::Lava => Some(CutScene::Won),
         }
     }
 
@@ -218,38 +297,41 @@ impl Map {
             Map::Ground => vec![
                 ActiveElement::Button {
                     position: Slot { column: 5, row: 7 },
-                    connected_wall: Slot { column: 2, row: 17 },
                 },
                 ActiveElement::Button {
                     position: Slot { column: 13, row: 9 },
-                    connected_wall: Slot { column: 10, row: 6 },
                 },
-                ActiveElement::Button {
+                ActiveElement::TimedButton {
                     position: Slot { column: 2, row: 1 },
-                    connected_wall: Slot { column: 8, row: 1 },
+                    seconds: 8,
+                },
+                ActiveElement::PressurePlate {
+                    position: Slot {
+                        column: 18,
+                        row: 16,
+                    },
                 },
             ],
             Map::Dirt => vec![
                 ActiveElement::Button {
                     position: Slot { column: 4, row: 14 },
-                    connected_wall: Slot { column: 18, row: 9 },
                 },
                 ActiveElement::Button {
                     position: Slot { column: 8, row: 11 },
-                    connected_wall: Slot { column: 14, row: 2 },
                 },
             ],
             Map::Stone => vec![
                 ActiveElement::Button {
                     position: Slot { column: 4, row: 16 },
-                    connected_wall: Slot { column: 7, row: 5 },
                 },
                 ActiveElement::Button {
                     position: Slot {
                         column: 17,
                         row: 11,
                     },
-                    connected_wall: Slot { column: 13, row: 3 },
+                },
+                ActiveElement::Lever {
+                    position: Slot { column: 16, row: 1 },
                 },
             ],
             Map::Lava => vec![
@@ -258,35 +340,184 @@ impl Map {
                         column: 21,
                         row: 14,
                     },
-                    connected_wall: Slot {
-                        column: 22,
-                        row: 18,
-                    },
                 },
                 ActiveElement::Button {
                     position: Slot { column: 8, row: 7 },
-                    connected_wall: Slot { column: 16, row: 6 },
                 },
                 ActiveElement::Button {
                     position: Slot { column: 19, row: 8 },
-                    connected_wall: Slot {
-                        column: 20,
-                        row: 16,
-                    },
                 },
             ],
         }
     }
+
+    // Walls are objects in the "wiring" layer with a RON "gate" property like `And([Input(0), Input(1)])`
+    // Gate inputs are indices into `active_elements`
+    pub fn wiring(&self, maps: &Assets<TiledMap>) -> Vec<WallWiring> {
+        self.objects(maps, "wiring")
+            .iter()
+            .filter_map(|object| {
+                Some(WallWiring {
+                    position: tiled_slot_at(object.x, object.y),
+                    gate: self.ron_property(object, "gate")?,
+                })
+            })
+            .collect()
+    }
+
+    fn objects<'a>(&self, maps: &'a Assets<TiledMap>, layer: &str) -> Vec<&'a tiled::Object> {
+        maps.get(&self.file()[..])
+            .map(|map| {
+                map.map
+                    .object_groups
+                    .iter()
+                    .filter(|group| group.name == layer)
+                    .flat_map(|group| group.objects.iter())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // A property holding RON like `Fox` or `And([Input(0), Input(1)])`
+    fn ron_property<T: DeserializeOwned>(&self, object: &tiled::Object, name: &str) -> Option<T> {
+        let value = match object.properties.get(name) {
+            Some(StringValue(value)) => value,
+            _ => {
+                warn!("Object {} in {} has no {}", object.id, self.file(), name);
+                return None;
+            }
+        };
+        match ron::de::from_str(value) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                warn!(
+                    "Failed to parse the {} of object {} in {}: {}",
+                    name,
+                    object.id,
+                    self.file(),
+                    error
+                );
+                None
+            }
+        }
+    }
+
+    // Objects in the "blocks" layer, one pushable block on each of their tiles
+    pub fn blocks(&self, maps: &Assets<TiledMap>) -> Vec<Slot> {
+        self.objects(maps, "blocks")
+            .iter()
+            .map(|object| tiled_slot_at(object.x, object.y))
+            .collect()
+    }
+
+    // Patrols are polylines in the "enemies" layer with a "kind" property like `Fox`
+    pub fn enemies(&self, maps: &Assets<TiledMap>) -> Vec<EnemySpawn> {
+        self.objects(maps, "enemies")
+            .iter()
+            .filter_map(|object| {
+                let points = match &object.shape {
+                    ObjectShape::Polyline { points } => points,
+                    _ => {
+                        warn!("Enemy {} in {} has no patrol path", object.id, self.file());
+                        return None;
+                    }
+                };
+                Some(EnemySpawn {
+                    kind: self.ron_property(object, "kind")?,
+                    patrol: points
+                        .iter()
+                        .map(|(x, y)| tiled_slot_at(object.x + x, object.y + y))
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+
+    // Objects in the "keys" layer with a "color" property like `Red`
+    pub fn keys(&self, maps: &Assets<TiledMap>) -> Vec<KeyPlacement> {
+        self.objects(maps, "keys")
+            .iter()
+            .filter_map(|object| {
+                Some(KeyPlacement {
+                    position: tiled_slot_at(object.x, object.y),
+                    color: self.ron_property(object, "color")?,
+                })
+            })
+            .collect()
+    }
+
+    // Objects in the "doors" layer, opened by the key of their "color"
+    pub fn locked_doors(&self, maps: &Assets<TiledMap>) -> Vec<DoorPlacement> {
+        self.objects(maps, "doors")
+            .iter()
+            .filter_map(|object| {
+                Some(DoorPlacement {
+                    position: tiled_slot_at(object.x, object.y),
+                    color: self.ron_property(object, "color")?,
+                })
+            })
+            .collect()
+    }
+
+    // Objects in the "collectibles" layer, one acorn on each of their tiles
+    pub fn collectibles(&self, maps: &Assets<TiledMap>) -> Vec<Slot> {
+        self.objects(maps, "collectibles")
+            .iter()
+            .map(|object| tiled_slot_at(object.x, object.y))
+            .collect()
+    }
+
+    // Finishing faster than this is worth a star
+    pub fn par_time(&self) -> Duration {
+        match self {
+            Map::Ground => Duration::from_secs(40),
+            Map::Dirt => Duration::from_secs(50),
+            Map::Stone => Duration::from_secs(60),
+            Map::Lava => Duration::from_secs(45),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub enum ActiveElement {
-    Button {
-        position: Slot,
-        connected_wall: Slot,
-    },
+    Button { position: Slot },
+    PressurePlate { position: Slot },
+    TimedButton { position: Slot, seconds: u64 },
+    Lever { position: Slot },
+}
+
+impl ActiveElement {
+    fn position(&self) -> &Slot {
+        match self {
+            ActiveElement::Button { position } => position,
+            ActiveElement::PressurePlate { position } => position,
+            ActiveElement::TimedButton { position, .. } => position,
+            ActiveElement::Lever { position } => position,
+        }
+    }
+
+    fn switch_kind(&self) -> SwitchKind {
+        match self {
+            ActiveElement::Button { .. } => SwitchKind::Button,
+            ActiveElement::PressurePlate { .. } => SwitchKind::PressurePlate,
+            ActiveElement::TimedButton { .. } => SwitchKind::TimedButton,
+            ActiveElement::Lever { .. } => SwitchKind::Lever,
+        }
+    }
+}
+
+// An exit without a target map ends the chase
+#[derive(Clone)]
+pub struct Exit {
+    pub position: Slot,
+    pub to: Option<Map>,
+}
+
+#[derive(Clone)]
+pub struct WallWiring {
+    pub position: Slot,
+    pub gate: Gate,
 }
-pub struct Trigger;
 
 fn load_map(current_map: Res<Map>, maps: Res<Assets<TiledMap>>) -> Option<MapData> {
     if !current_map.is_added() && !current_map.is_changed() {
@@ -295,12 +526,16 @@ fn load_map(current_map: Res<Map>, maps: Res<Assets<TiledMap>>) -> Option<MapDat
     if let Some(map) = maps.get(&current_map.file()[..]) {
         let map = &map.map;
         let mut path_map: HashMap<u32, String> = HashMap::default();
+        let mut diggable: HashSet<u32> = HashSet::default();
         for set in map.tilesets.iter() {
             for tile in set.tiles.iter() {
                 path_map.insert(
                     set.first_gid + tile.id,
                     tile.images.first().unwrap().source.clone(),
                 );
+                if let Some(BoolValue(true)) = tile.properties.get("diggable") {
+                    diggable.insert(set.first_gid + tile.id);
+                }
             }
         }
 
@@ -318,19 +553,16 @@ fn load_map(current_map: Res<Map>, maps: Res<Assets<TiledMap>>) -> Option<MapDat
             }
             layers.push(current_layer);
         }
-        let colliding: Vec<usize> = map
-            .layers
-            .iter()
-            .enumerate()
-            .filter(|(_index, layer)| {
-                if let Some(BoolValue(collide)) = layer.properties.get("collide") {
-                    return collide.clone();
-                }
-                false
-            })
-            .map(|(index, _layer)| index)
-            .collect();
+        let colliding = layers_with_property(map, "collide");
+        let hazards = layers_with_property(map, "hazard");
+        let checkpoints = layers_with_property(map, "checkpoint");
+        let crumbling = layers_with_property(map, "crumble");
+        let slow = layers_with_property(map, "slow");
         let mut colliding_layers: Vec<bool> = vec![];
+        let mut hazard_layers: Vec<bool> = vec![];
+        let mut checkpoint_layers: Vec<bool> = vec![];
+        let mut crumbling_layers: Vec<bool> = vec![];
+        let mut slow_layers: Vec<bool> = vec![];
         let mut tile_layers: Vec<Vec<Vec<Tile>>> = vec![];
         for (floor_index, layer_data) in layers.iter().enumerate() {
             let mut floor = vec![];
@@ -340,9 +572,13 @@ fn load_map(current_map: Res<Map>, maps: Res<Assets<TiledMap>>) -> Option<MapDat
                     if let Some(path) = path_map.get(gid) {
                         row.push(Tile {
                             asset_path: Some(path.clone()),
+                            diggable: diggable.contains(gid),
                         })
                     } else {
-                        row.push(Tile { asset_path: None })
+                        row.push(Tile {
+                            asset_path: None,
+                            diggable: false,
+                        })
                     }
                 }
                 floor.push(row);
@@ -350,6 +586,10 @@ fn load_map(current_map: Res<Map>, maps: Res<Assets<TiledMap>>) -> Option<MapDat
             // otherwise the map is upside down O.o
             floor.reverse();
             colliding_layers.push(colliding.contains(&floor_index));
+            hazard_layers.push(hazards.contains(&floor_index));
+            checkpoint_layers.push(checkpoints.contains(&floor_index));
+            crumbling_layers.push(crumbling.contains(&floor_index));
+            slow_layers.push(slow.contains(&floor_index));
             tile_layers.push(floor);
         }
         return Some(MapData {
@@ -357,11 +597,65 @@ fn load_map(current_map: Res<Map>, maps: Res<Assets<TiledMap>>) -> Option<MapDat
             height: map.height as usize,
             width: map.width as usize,
             colliding_layers,
+            hazard_layers,
+            checkpoint_layers,
+            crumbling_layers,
+            slow_layers,
+            platform_paths: platform_paths(map),
         });
     }
     None
 }
 
+// Polylines in the "platforms" object layer converted to world positions
+fn platform_paths(map: &tiled::Map) -> Vec<Vec<Vec2>> {
+    let height = map.height as f32 * TILE_SIZE;
+    map.object_groups
+        .iter()
+        .filter(|group| group.name == "platforms")
+        .flat_map(|group| group.objects.iter())
+        .filter_map(|object| {
+            if let ObjectShape::Polyline { points } = &object.shape {
+                Some(
+                    points
+                        .iter()
+                        .map(|(x, y)| {
+                            Vec2::new(
+                                object.x + x - TILE_SIZE / 2.,
+                                height - (object.y + y) - TILE_SIZE / 2.,
+                            )
+                        })
+                        .collect(),
+                )
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// The tiled slot containing a position in the level file
+fn tiled_slot_at(x: f32, y: f32) -> Slot {
+    Slot {
+        column: (x / TILE_SIZE) as usize,
+        row: (y / TILE_SIZE) as usize,
+    }
+}
+
+fn layers_with_property(map: &tiled::Map, property: &str) -> Vec<usize> {
+    map.layers
+        .iter()
+        .enumerate()
+        .filter(|(_index, layer)| {
+            if let Some(BoolValue(value)) = layer.properties.get(property) {
+                return value.clone();
+            }
+            false
+        })
+        .map(|(index, _layer)| index)
+        .collect()
+}
+
 fn draw_map(
     map_data: In<Option<MapData>>,
     mut commands: Commands,
@@ -372,6 +666,8 @@ fn draw_map(
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut trigger_scene: EventWriter<TriggerScene>,
     tiles: Query<Entity, With<MapTile>>,
+    platforms: Query<Entity, With<Platform>>,
+    acorns: Query<Entity, With<Acorn>>,
 ) {
     if map_data.0.is_none() {
         return;
@@ -386,16 +682,44 @@ fn draw_map(
             .get(layer_index)
             .unwrap_or(&false)
             .clone();
+        let hazard = *map_data.hazard_layers.get(layer_index).unwrap_or(&false);
+        let checkpoint = *map_data
+            .checkpoint_layers
+            .get(layer_index)
+            .unwrap_or(&false);
+        let crumbling = *map_data.crumbling_layers.get(layer_index).unwrap_or(&false);
+        let slow = *map_data.slow_layers.get(layer_index).unwrap_or(&false);
         for row in 0..map_data.height {
             for column in 0..map_data.width {
                 let tile = &layer[row][column];
                 if let Some(path) = &tile.asset_path {
+                    let texture = asset_server.get_handle(&(path)[3..]);
+                    let (material, z) = if hazard {
+                        (ColorMaterial::modulated_texture(texture, HAZARD_TINT), 0.1)
+                    } else if checkpoint {
+                        (
+                            ColorMaterial::modulated_texture(texture, CHECKPOINT_TINT),
+                            0.1,
+                        )
+                    } else if crumbling {
+                        (
+                            ColorMaterial::modulated_texture(texture, CRUMBLING_TINT),
+                            0.1,
+                        )
+                    } else if slow {
+                        (
+                            ColorMaterial::modulated_texture(texture, SLOW_TILE_TINT),
+                            0.1,
+                        )
+                    } else {
+                        (texture.into(), 0.)
+                    };
                     let sprite = SpriteBundle {
-                        material: materials.add(asset_server.get_handle(&(path)[3..]).into()),
+                        material: materials.add(material),
                         transform: Transform::from_translation(Vec3::new(
                             column as f32 * TILE_SIZE,
                             row as f32 * TILE_SIZE,
-                            0.,
+                            z,
                         )),
                         ..Default::default()
                     };
@@ -404,18 +728,47 @@ fn draw_map(
                         row,
                         tile: tile.clone(),
                     };
+                    let mut entity = commands.spawn_bundle(sprite);
+                    entity.insert(tile).insert(LevelEntity);
                     if collide {
-                        commands
-                            .spawn_bundle(sprite)
-                            .insert(tile)
-                            .insert(Collide { x: column, y: row });
-                    } else {
-                        commands.spawn_bundle(sprite).insert(tile);
+                        entity.insert(Collide { x: column, y: row });
+                    }
+                    if hazard {
+                        entity.insert(Hazard);
+                    }
+                    if checkpoint {
+                        entity.insert(Checkpoint);
+                    }
+                    if crumbling {
+                        entity.insert(CrumblingTile);
+                    }
+                    if slow {
+                        entity.insert(SlowTile);
                     }
                 }
             }
         }
     }
+    // on a restart the acorn of the last attempt may still wait at the goal or flee
+    for entity in platforms.iter().chain(acorns.iter()) {
+        commands.entity(entity).despawn();
+    }
+    for path in map_data.platform_paths {
+        if path.is_empty() {
+            continue;
+        }
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: materials.add(ColorMaterial::modulated_texture(
+                    texture_assets.texture_wall_down.clone(),
+                    PLATFORM_TINT,
+                )),
+                transform: Transform::from_translation(path[0].extend(PLATFORM_Z)),
+                ..Default::default()
+            })
+            .insert(Platform::new(path))
+            .insert(LevelEntity);
+    }
     let acorn_position = current_map.acorn_position();
     commands
         .spawn_bundle(SpriteBundle {
@@ -427,45 +780,48 @@ fn draw_map(
             )),
             ..Default::default()
         })
-        .insert(Acorn);
+        .insert(Acorn)
+        .insert(LevelEntity);
     let window = windows.get_primary().expect("No primary window");
     if let Some(scene) = current_map.intro_scene(window) {
         trigger_scene.send(TriggerScene { scene });
     }
 }
 
-pub struct ButtonWall {
-    button_slot: Slot,
-    wall_slot: Slot,
-    button: Entity,
-    wall: Entity,
+pub struct ActiveElementTile;
+
+fn remove_level(
+    mut commands: Commands,
+    level: Query<Entity, Or<(With<LevelEntity>, With<Camera>)>>,
+) {
+    // the menu brings its own cameras
+    for entity in level.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
 }
 
 fn draw_active_elements(
     mut commands: Commands,
     current_map: Res<Map>,
-    elements: Query<Entity, With<ButtonWall>>,
+    maps: Res<Assets<TiledMap>>,
+    elements: Query<Entity, With<ActiveElementTile>>,
+    countdowns: Query<Entity, With<Countdown>>,
     textures: Res<TextureAssets>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
     if !current_map.is_added() && !current_map.is_changed() {
         return;
     }
-    for entity in elements.iter() {
+    for entity in elements.iter().chain(countdowns.iter()) {
         commands.entity(entity).despawn();
     }
     let active_elements = current_map.active_elements();
-    for element in active_elements {
-        let ActiveElement::Button {
-            position,
-            connected_wall,
-        } = element.clone();
-
-        let button_slot = current_map.tiled_slot_to_bevy_slot(position.clone());
-        let connected_wall_slot = current_map.tiled_slot_to_bevy_slot(connected_wall.clone());
+    for (index, element) in active_elements.iter().enumerate() {
+        let button_slot = current_map.tiled_slot_to_bevy_slot(element.position().clone());
+        let kind = element.switch_kind();
         let button = commands
             .spawn_bundle(SpriteBundle {
-                material: materials.add(textures.texture_button_up.clone().into()),
+                material: materials.add(switch_material(kind, false, &textures)),
                 transform: Transform::from_translation(Vec3::new(
                     button_slot.column as f32 * TILE_SIZE,
                     button_slot.row as f32 * TILE_SIZE,
@@ -473,89 +829,130 @@ fn draw_active_elements(
                 )),
                 ..Default::default()
             })
+            .insert(ActiveElementTile)
+            .insert(LevelEntity)
+            .insert(Switch {
+                index,
+                kind,
+                active: false,
+            })
             .id();
-        commands.entity(button).insert(Trigger);
+        match element {
+            ActiveElement::Button { .. } => {
+                commands.entity(button).insert(Trigger);
+            }
+            ActiveElement::PressurePlate { .. } => {
+                commands.entity(button).insert(PressurePlate);
+            }
+            ActiveElement::TimedButton { seconds, .. } => {
+                commands.entity(button).insert(Trigger).insert(TimedButton {
+                    duration: Duration::from_secs(*seconds),
+                });
+            }
+            ActiveElement::Lever { .. } => {
+                commands.entity(button).insert(Lever { touching: false });
+            }
+        }
+    }
+
+    let signals = vec![false; active_elements.len()];
+    for wiring in current_map.wiring(&maps) {
+        let wall_slot = current_map.tiled_slot_to_bevy_slot(wiring.position.clone());
+        let open = wiring.gate.evaluate(&signals);
+        let texture = if open {
+            textures.texture_wall_down.clone()
+        } else {
+            textures.texture_wall_up.clone()
+        };
         let wall = commands
             .spawn_bundle(SpriteBundle {
-                material: materials.add(textures.texture_wall_up.clone().into()),
+                material: materials.add(texture.into()),
                 transform: Transform::from_translation(Vec3::new(
-                    connected_wall_slot.column as f32 * TILE_SIZE,
-                    connected_wall_slot.row as f32 * TILE_SIZE,
+                    wall_slot.column as f32 * TILE_SIZE,
+                    wall_slot.row as f32 * TILE_SIZE,
                     ACTIVE_ELEMENT_Z,
                 )),
                 ..Default::default()
             })
+            .insert(ActiveElementTile)
+            .insert(LevelEntity)
             .id();
-        commands.entity(wall).insert(Collide {
-            x: connected_wall_slot.column,
-            y: connected_wall_slot.row,
-        });
-
-        commands.entity(wall).insert(ButtonWall {
-            button: button.clone(),
-            wall: wall.clone(),
-            button_slot: position.clone(),
-            wall_slot: connected_wall.clone(),
-        });
-        commands.entity(button).insert(ButtonWall {
-            button: button.clone(),
-            wall: wall.clone(),
-            button_slot: position.clone(),
-            wall_slot: connected_wall.clone(),
+        if !open {
+            commands.entity(wall).insert(Collide {
+                x: wall_slot.column,
+                y: wall_slot.row,
+            });
+        }
+        commands.entity(wall).insert(Wall {
+            gate: wiring.gate,
+            open,
+            slot: wall_slot,
         });
     }
 }
 
-fn check_active_elements(
-    mut commands: Commands,
-    current_map: Res<Map>,
-    game_state: Res<GameData>,
-    windows: Res<Windows>,
-    mut elements: Query<(Entity, &Transform, &ButtonWall), With<Trigger>>,
-    player_query: Query<&Transform, With<Player>>,
-    mut trigger_scene: EventWriter<TriggerScene>,
-) {
-    if game_state.frozen {
-        return;
+pub fn is_touching(transform: &Transform, other: &Transform) -> bool {
+    Vec2::new(transform.translation.x, transform.translation.y)
+        .distance(Vec2::new(other.translation.x, other.translation.y))
+        < 25.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::asset::AssetPlugin;
+    use bevy::window::WindowId;
+
+    fn empty_level() -> Option<MapData> {
+        Some(MapData {
+            layers: vec![],
+            colliding_layers: vec![],
+            hazard_layers: vec![],
+            checkpoint_layers: vec![],
+            crumbling_layers: vec![],
+            slow_layers: vec![],
+            platform_paths: vec![],
+            height: 0,
+            width: 0,
+        })
     }
-    if let Ok(player_transform) = player_query.single() {
-        for (entity, transform, element) in elements.iter_mut() {
-            if Vec2::new(
-                player_transform.translation.x,
-                player_transform.translation.y,
-            )
-            .distance(Vec2::new(transform.translation.x, transform.translation.y))
-                < 25.
-            {
-                commands.entity(entity).remove::<Trigger>();
-                let ButtonWall {
-                    button_slot: _button_slot,
-                    wall_slot,
-                    button,
-                    wall,
-                } = element;
-
-                let wall_position = current_map.position_from_slot(wall_slot.clone());
-                let window = windows.get_primary().expect("No primary window");
-                trigger_scene.send(TriggerScene {
-                    scene: CutScene::ActivateButton {
-                        button: button.clone(),
-                        wall: wall.clone(),
-                        camera_from: calc_camera_position(
-                            player_transform.translation.x,
-                            player_transform.translation.y,
-                            window,
-                            &current_map.dimensions(),
-                        ),
-                        camera_to: calc_camera_position(
-                            wall_position.0,
-                            wall_position.1,
-                            window,
-                            &current_map.dimensions(),
-                        ),
-                    },
-                });
-            }
-        }
+
+    #[test]
+    fn restarting_keeps_one_acorn() {
+        let mut windows = Windows::default();
+        windows.add(Window::new(
+            WindowId::primary(),
+            &WindowDescriptor::default(),
+            800,
+            600,
+            1.,
+            None,
+        ));
+        let mut app = App::build();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin)
+            .add_asset::<Texture>()
+            .add_asset::<ColorMaterial>()
+            .add_event::<TriggerScene>()
+            .insert_resource(Map::Lava)
+            .insert_resource(windows)
+            .insert_resource(TextureAssets {
+                texture_player: Handle::default(),
+                texture_button_up: Handle::default(),
+                texture_acorn: Handle::default(),
+                texture_button_down: Handle::default(),
+                texture_wall_up: Handle::default(),
+                texture_wall_down: Handle::default(),
+                texture_menu: Handle::default(),
+            })
+            .add_system(empty_level.system().chain(draw_map.system()));
+
+        // every frame draws the level again, like a restart does
+        app.app.update();
+        app.app.update();
+
+        let world = &mut app.app.world;
+        let mut acorns = world.query_filtered::<Entity, With<Acorn>>();
+        assert_eq!(acorns.iter(world).count(), 1);
     }
 }
@@ -1,18 +1,35 @@
+use crate::actions::{ControlScheme, ControlSettings};
 use crate::audio::BackgroundAudio;
-use crate::loading::{AudioAssets, TextureAssets};
+use crate::loading::{AudioAssets, FontAssets, TextureAssets};
+use crate::map::Map;
 use crate::map::TILE_SIZE;
 use crate::player::PlayerCamera;
-use crate::GameState;
+use crate::save::{now, LoadGame, SaveGame, SaveSlots};
+use crate::score::{BestScores, Playtime};
+use crate::settings::{spawn_settings_panel, Settings, SettingsPanel};
+use crate::skip::SeenScenes;
+use crate::storage::GameStorage;
+use crate::{GameData, GameState};
+use bevy::ecs::component::Component;
 use bevy::prelude::*;
+use bevy::utils::Duration;
 
 pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.init_resource::<ButtonMaterials>()
+            .init_resource::<PendingSlotChoice>()
             .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(setup_menu.system()))
             .add_system_set(
-                SystemSet::on_update(GameState::Menu).with_system(click_play_button.system()),
+                SystemSet::on_update(GameState::Menu)
+                    .with_system(click_play_button.system())
+                    .with_system(click_continue_button.system())
+                    .with_system(click_slot_button.system())
+                    .with_system(click_confirm_button.system())
+                    .with_system(click_acorn_mode_button.system())
+                    .with_system(click_control_scheme_button.system())
+                    .with_system(click_open_settings_button.system()),
             );
     }
 }
@@ -33,6 +50,34 @@ impl FromWorld for ButtonMaterials {
 }
 
 struct PlayButton;
+struct ContinueButton;
+struct SlotMenu;
+struct ConfirmDialog;
+
+#[derive(Clone, Copy, PartialEq)]
+enum SlotAction {
+    Load,
+    New,
+    Delete,
+}
+
+struct SlotButton {
+    slot: usize,
+    action: SlotAction,
+}
+
+struct ConfirmButton {
+    confirm: bool,
+}
+
+// An overwrite or delete waiting for confirmation
+#[derive(Default)]
+struct PendingSlotChoice {
+    choice: Option<(usize, SlotAction)>,
+}
+struct AcornModeButton;
+struct ControlSchemeButton;
+struct OpenSettingsButton;
 struct Menu;
 
 fn setup_menu(
@@ -42,6 +87,9 @@ fn setup_menu(
     mut materials: ResMut<Assets<ColorMaterial>>,
     audio_assets: Res<AudioAssets>,
     texture_assets: Res<TextureAssets>,
+    game_state: Res<GameData>,
+    controls: Res<ControlSettings>,
+    save_slots: Res<SaveSlots>,
     mut background_audio: EventWriter<BackgroundAudio>,
 ) {
     background_audio.send(BackgroundAudio {
@@ -104,6 +152,133 @@ fn setup_menu(
                 ..Default::default()
             });
         });
+    if save_slots.latest().is_some() {
+        commands
+            .spawn_bundle(ButtonBundle {
+                style: Style {
+                    size: Size::new(Val::Px(160.0), Val::Px(50.0)),
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        left: Val::Percent(50.),
+                        top: Val::Percent(60.),
+                        ..Default::default()
+                    },
+                    margin: Rect {
+                        left: Val::Px(-80.),
+                        ..Default::default()
+                    },
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                material: button_materials.normal.clone(),
+                ..Default::default()
+            })
+            .insert(ContinueButton)
+            .insert(Menu)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection {
+                            value: "Continue".to_string(),
+                            style: TextStyle {
+                                font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                                font_size: 40.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                            },
+                        }],
+                        alignment: Default::default(),
+                    },
+                    ..Default::default()
+                });
+            });
+    }
+    let acorn_mode_button = spawn_option_button(
+        &mut commands,
+        &asset_server,
+        &button_materials,
+        10.,
+        acorn_mode_text(game_state.fleeing_acorn),
+    );
+    commands.entity(acorn_mode_button).insert(AcornModeButton);
+    let control_scheme_button = spawn_option_button(
+        &mut commands,
+        &asset_server,
+        &button_materials,
+        60.,
+        control_scheme_text(controls.scheme),
+    );
+    commands
+        .entity(control_scheme_button)
+        .insert(ControlSchemeButton);
+    let settings_button = spawn_option_button(
+        &mut commands,
+        &asset_server,
+        &button_materials,
+        110.,
+        "Settings",
+    );
+    commands.entity(settings_button).insert(OpenSettingsButton);
+}
+
+// A small button in the bottom right corner of the menu
+fn spawn_option_button(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    button_materials: &ButtonMaterials,
+    bottom: f32,
+    text: &str,
+) -> Entity {
+    commands
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(240.0), Val::Px(40.0)),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    bottom: Val::Px(bottom),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: button_materials.normal.clone(),
+            ..Default::default()
+        })
+        .insert(Menu)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text {
+                    sections: vec![TextSection {
+                        value: text.to_string(),
+                        style: TextStyle {
+                            font: asset_server.get_handle("fonts/FiraSans-Bold.ttf"),
+                            font_size: 26.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    }],
+                    alignment: Default::default(),
+                },
+                ..Default::default()
+            });
+        })
+        .id()
+}
+
+fn control_scheme_text(scheme: ControlScheme) -> &'static str {
+    match scheme {
+        ControlScheme::Free => "Controls: free",
+        ControlScheme::GridStep => "Controls: grid steps",
+    }
+}
+
+fn acorn_mode_text(fleeing: bool) -> &'static str {
+    if fleeing {
+        "Acorn: runs away"
+    } else {
+        "Acorn: falls down"
+    }
 }
 
 type ButtonInteraction<'a> = (
@@ -115,22 +290,598 @@ type ButtonInteraction<'a> = (
 
 fn click_play_button(
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    button_materials: Res<ButtonMaterials>,
+    save_slots: Res<SaveSlots>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut interaction_query: Query<ButtonInteraction, (Changed<Interaction>, With<PlayButton>)>,
+    slot_menu: Query<Entity, With<SlotMenu>>,
+    settings_panels: Query<Entity, With<SettingsPanel>>,
+) {
+    for (_button, interaction, mut material, _children) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                for panel in settings_panels.iter() {
+                    commands.entity(panel).despawn_recursive();
+                }
+                if slot_menu.iter().next().is_none() {
+                    spawn_slot_menu(
+                        &mut commands,
+                        &asset_server,
+                        &button_materials,
+                        &mut materials,
+                        &save_slots,
+                    );
+                }
+            }
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
+
+fn click_continue_button(
+    mut commands: Commands,
+    button_materials: Res<ButtonMaterials>,
+    mut state: ResMut<State<GameState>>,
+    mut game_state: ResMut<GameData>,
+    mut current_map: ResMut<Map>,
+    mut save_slots: ResMut<SaveSlots>,
+    mut playtime: ResMut<Playtime>,
+    mut best_scores: ResMut<BestScores>,
+    mut seen_scenes: ResMut<SeenScenes>,
+    mut interaction_query: Query<ButtonInteraction, (Changed<Interaction>, With<ContinueButton>)>,
+    menu_query: Query<Entity, Or<(With<Menu>, With<PlayButton>)>>,
+) {
+    for (_button, interaction, mut material, _children) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                let slot = match save_slots.latest() {
+                    Some(slot) => slot,
+                    None => continue,
+                };
+                let save = save_slots.slots[slot].clone();
+                start_game(
+                    &mut commands,
+                    &mut state,
+                    &mut game_state,
+                    &mut current_map,
+                    &mut save_slots,
+                    &mut playtime,
+                    &mut best_scores,
+                    &mut seen_scenes,
+                    &menu_query,
+                    slot,
+                    save,
+                );
+            }
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
+
+// Start a new game in the slot or continue the given save
+fn start_game(
+    commands: &mut Commands,
+    state: &mut State<GameState>,
+    game_state: &mut GameData,
+    current_map: &mut Map,
+    save_slots: &mut SaveSlots,
+    playtime: &mut Playtime,
+    best_scores: &mut BestScores,
+    seen_scenes: &mut SeenScenes,
+    menu_query: &Query<Entity, Or<(With<Menu>, With<PlayButton>)>>,
+    slot: usize,
+    save: Option<SaveGame>,
+) {
+    for entity in menu_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    save_slots.active = Some(slot);
+    match save {
+        Some(save) => {
+            game_state.fleeing_acorn = save.fleeing_acorn;
+            *current_map = save.level.clone();
+            playtime.total = Duration::from_secs_f32(save.total_time);
+            best_scores.levels = save.best.clone();
+            seen_scenes.scenes = save.seen_scenes.clone();
+            commands.insert_resource(LoadGame::new(save));
+        }
+        None => {
+            *current_map = Map::Ground;
+            playtime.total = Duration::default();
+            best_scores.levels.clear();
+            seen_scenes.scenes.clear();
+        }
+    }
+    state.set(GameState::Playing).unwrap();
+}
+
+fn spawn_slot_menu(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    button_materials: &ButtonMaterials,
+    materials: &mut Assets<ColorMaterial>,
+    save_slots: &SaveSlots,
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    let now = now();
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(20.),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(SlotMenu)
+        .insert(Menu)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        padding: Rect::all(Val::Px(10.)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::rgba(0.1, 0.1, 0.1, 0.8).into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for (slot, save) in save_slots.slots.iter().enumerate() {
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                style: Style {
+                                    flex_direction: FlexDirection::Row,
+                                    ..Default::default()
+                                },
+                                material: materials.add(Color::NONE.into()),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                let summary = slot_summary(slot, save.as_ref(), now);
+                                let action = if save.is_some() {
+                                    SlotAction::Load
+                                } else {
+                                    SlotAction::New
+                                };
+                                spawn_panel_button(
+                                    parent,
+                                    button_materials,
+                                    &font,
+                                    SlotButton { slot, action },
+                                    420.,
+                                    &summary,
+                                );
+                                if save.is_some() {
+                                    spawn_panel_button(
+                                        parent,
+                                        button_materials,
+                                        &font,
+                                        SlotButton {
+                                            slot,
+                                            action: SlotAction::New,
+                                        },
+                                        80.,
+                                        "New",
+                                    );
+                                    spawn_panel_button(
+                                        parent,
+                                        button_materials,
+                                        &font,
+                                        SlotButton {
+                                            slot,
+                                            action: SlotAction::Delete,
+                                        },
+                                        90.,
+                                        "Delete",
+                                    );
+                                }
+                            });
+                    }
+                });
+        });
+}
+
+pub fn spawn_panel_button<T: Component>(
+    parent: &mut ChildBuilder,
+    button_materials: &ButtonMaterials,
+    font: &Handle<Font>,
+    marker: T,
+    width: f32,
+    text: &str,
+) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(width), Val::Px(40.0)),
+                margin: Rect::all(Val::Px(4.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: button_materials.normal.clone(),
+            ..Default::default()
+        })
+        .insert(marker)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    text,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 22.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+fn slot_summary(slot: usize, save: Option<&SaveGame>, now: u64) -> String {
+    let save = match save {
+        Some(save) => save,
+        None => return format!("Slot {}: empty", slot + 1),
+    };
+    let total = save.total_time as u64;
+    format!(
+        "Slot {}: {}, {}:{:02}, {} stars, {}",
+        slot + 1,
+        save.level.name(),
+        total / 60,
+        total % 60,
+        save.stars(),
+        last_played_text(save.last_played, now)
+    )
+}
+
+fn last_played_text(last_played: u64, now: u64) -> String {
+    if last_played == 0 {
+        return "a while ago".to_owned();
+    }
+    let seconds = now.saturating_sub(last_played);
+    match seconds {
+        0..=59 => "just now".to_owned(),
+        60..=3599 => format!("{} min ago", seconds / 60),
+        3600..=86399 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400),
+    }
+}
+
+fn click_slot_button(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
     button_materials: Res<ButtonMaterials>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut pending: ResMut<PendingSlotChoice>,
     mut state: ResMut<State<GameState>>,
-    mut interaction_query: Query<ButtonInteraction, (Changed<Interaction>, With<Button>)>,
-    text_query: Query<Entity, With<Text>>,
-    menu_query: Query<Entity, With<Menu>>,
+    mut game_state: ResMut<GameData>,
+    mut current_map: ResMut<Map>,
+    mut save_slots: ResMut<SaveSlots>,
+    mut playtime: ResMut<Playtime>,
+    mut best_scores: ResMut<BestScores>,
+    mut seen_scenes: ResMut<SeenScenes>,
+    mut interaction_query: Query<
+        (&SlotButton, &Interaction, &mut Handle<ColorMaterial>),
+        Changed<Interaction>,
+    >,
+    dialog: Query<Entity, With<ConfirmDialog>>,
+    menu_query: Query<Entity, Or<(With<Menu>, With<PlayButton>)>>,
+) {
+    for (button, interaction, mut material) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                if dialog.iter().next().is_some() {
+                    continue;
+                }
+                let save = save_slots.slots[button.slot].clone();
+                match (button.action, save) {
+                    (SlotAction::Load, Some(save)) => start_game(
+                        &mut commands,
+                        &mut state,
+                        &mut game_state,
+                        &mut current_map,
+                        &mut save_slots,
+                        &mut playtime,
+                        &mut best_scores,
+                        &mut seen_scenes,
+                        &menu_query,
+                        button.slot,
+                        Some(save),
+                    ),
+                    (SlotAction::New, None) | (SlotAction::Load, None) => start_game(
+                        &mut commands,
+                        &mut state,
+                        &mut game_state,
+                        &mut current_map,
+                        &mut save_slots,
+                        &mut playtime,
+                        &mut best_scores,
+                        &mut seen_scenes,
+                        &menu_query,
+                        button.slot,
+                        None,
+                    ),
+                    (action, _) => {
+                        pending.choice = Some((button.slot, action));
+                        let question = if action == SlotAction::Delete {
+                            format!("Delete slot {}?", button.slot + 1)
+                        } else {
+                            format!("Overwrite slot {}?", button.slot + 1)
+                        };
+                        spawn_confirm_dialog(
+                            &mut commands,
+                            &asset_server,
+                            &button_materials,
+                            &mut materials,
+                            &question,
+                        );
+                    }
+                }
+            }
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
+
+fn spawn_confirm_dialog(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    button_materials: &ButtonMaterials,
+    materials: &mut Assets<ColorMaterial>,
+    question: &str,
+) {
+    let font = asset_server.get_handle("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(220.),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(ConfirmDialog)
+        .insert(Menu)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::Center,
+                        padding: Rect::all(Val::Px(10.)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::rgba(0.2, 0.1, 0.1, 0.9).into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        style: Style {
+                            margin: Rect::all(Val::Px(8.)),
+                            ..Default::default()
+                        },
+                        text: Text::with_section(
+                            question,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 26.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    });
+                    spawn_panel_button(
+                        parent,
+                        button_materials,
+                        &font,
+                        ConfirmButton { confirm: true },
+                        70.,
+                        "Yes",
+                    );
+                    spawn_panel_button(
+                        parent,
+                        button_materials,
+                        &font,
+                        ConfirmButton { confirm: false },
+                        70.,
+                        "No",
+                    );
+                });
+        });
+}
+
+fn click_confirm_button(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    button_materials: Res<ButtonMaterials>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut pending: ResMut<PendingSlotChoice>,
+    mut state: ResMut<State<GameState>>,
+    mut game_state: ResMut<GameData>,
+    mut current_map: ResMut<Map>,
+    mut save_slots: ResMut<SaveSlots>,
+    mut storage: ResMut<GameStorage>,
+    mut playtime: ResMut<Playtime>,
+    mut best_scores: ResMut<BestScores>,
+    mut seen_scenes: ResMut<SeenScenes>,
+    mut interaction_query: Query<
+        (&ConfirmButton, &Interaction, &mut Handle<ColorMaterial>),
+        Changed<Interaction>,
+    >,
+    dialogs: Query<Entity, Or<(With<ConfirmDialog>, With<SlotMenu>)>>,
+    menu_query: Query<Entity, Or<(With<Menu>, With<PlayButton>)>>,
+) {
+    for (button, interaction, mut material) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                let (slot, action) = match pending.choice.take() {
+                    Some(choice) => choice,
+                    None => continue,
+                };
+                if !button.confirm {
+                    for entity in dialogs.iter() {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                    spawn_slot_menu(
+                        &mut commands,
+                        &asset_server,
+                        &button_materials,
+                        &mut materials,
+                        &save_slots,
+                    );
+                    continue;
+                }
+                save_slots.delete(&mut storage, slot);
+                if action == SlotAction::New {
+                    start_game(
+                        &mut commands,
+                        &mut state,
+                        &mut game_state,
+                        &mut current_map,
+                        &mut save_slots,
+                        &mut playtime,
+                        &mut best_scores,
+                        &mut seen_scenes,
+                        &menu_query,
+                        slot,
+                        None,
+                    );
+                } else {
+                    for entity in dialogs.iter() {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                    spawn_slot_menu(
+                        &mut commands,
+                        &asset_server,
+                        &button_materials,
+                        &mut materials,
+                        &save_slots,
+                    );
+                }
+            }
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
+
+fn click_acorn_mode_button(
+    button_materials: Res<ButtonMaterials>,
+    mut game_state: ResMut<GameData>,
+    mut interaction_query: Query<ButtonInteraction, (Changed<Interaction>, With<AcornModeButton>)>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (_button, interaction, mut material, children) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                game_state.fleeing_acorn = !game_state.fleeing_acorn;
+                if let Ok(mut text) = text_query.get_mut(children[0]) {
+                    text.sections[0].value = acorn_mode_text(game_state.fleeing_acorn).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
+
+fn click_control_scheme_button(
+    button_materials: Res<ButtonMaterials>,
+    mut controls: ResMut<ControlSettings>,
+    mut interaction_query: Query<
+        ButtonInteraction,
+        (Changed<Interaction>, With<ControlSchemeButton>),
+    >,
+    mut text_query: Query<&mut Text>,
+) {
+    for (_button, interaction, mut material, children) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                controls.scheme = match controls.scheme {
+                    ControlScheme::Free => ControlScheme::GridStep,
+                    ControlScheme::GridStep => ControlScheme::Free,
+                };
+                if let Ok(mut text) = text_query.get_mut(children[0]) {
+                    text.sections[0].value = control_scheme_text(controls.scheme).to_string();
+                }
+            }
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
+
+fn click_open_settings_button(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+    settings: Res<Settings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut pending: ResMut<PendingSlotChoice>,
+    mut interaction_query: Query<
+        ButtonInteraction,
+        (Changed<Interaction>, With<OpenSettingsButton>),
+    >,
+    dialogs: Query<Entity, Or<(With<ConfirmDialog>, With<SlotMenu>, With<SettingsPanel>)>>,
 ) {
-    for (button, interaction, mut material, children) in interaction_query.iter_mut() {
-        let text = text_query.get(children[0]).unwrap();
+    for (_button, interaction, mut material, _children) in interaction_query.iter_mut() {
         match *interaction {
             Interaction::Clicked => {
-                for entity in menu_query.iter() {
-                    commands.entity(entity).despawn();
+                for entity in dialogs.iter() {
+                    commands.entity(entity).despawn_recursive();
                 }
-                commands.entity(button).despawn();
-                commands.entity(text).despawn();
-                state.set(GameState::Playing).unwrap();
+                pending.choice = None;
+                let panel = spawn_settings_panel(
+                    &mut commands,
+                    &font_assets,
+                    &button_materials,
+                    &mut materials,
+                    &settings,
+                );
+                commands.entity(panel).insert(Menu);
             }
             Interaction::Hovered => {
                 *material = button_materials.hovered.clone();
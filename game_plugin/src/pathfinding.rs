@@ -0,0 +1,96 @@
+use crate::map::{Collide, Dimensions, Slot, TILE_SIZE};
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+pub struct Grid {
+    blocked: HashSet<Slot>,
+    columns: usize,
+    rows: usize,
+}
+
+impl Grid {
+    pub fn new<'a>(dimensions: &Dimensions, colliders: impl Iterator<Item = &'a Collide>) -> Self {
+        Grid {
+            blocked: colliders
+                .map(|collide| Slot {
+                    column: collide.x,
+                    row: collide.y,
+                })
+                .collect(),
+            columns: dimensions.columns,
+            rows: dimensions.rows,
+        }
+    }
+
+    pub fn is_free(&self, slot: &Slot) -> bool {
+        slot.column < self.columns && slot.row < self.rows && !self.blocked.contains(slot)
+    }
+
+    pub fn neighbours(&self, slot: &Slot) -> Vec<Slot> {
+        let mut neighbours = vec![];
+        if slot.column > 0 {
+            neighbours.push(Slot {
+                column: slot.column - 1,
+                row: slot.row,
+            });
+        }
+        if slot.row > 0 {
+            neighbours.push(Slot {
+                column: slot.column,
+                row: slot.row - 1,
+            });
+        }
+        neighbours.push(Slot {
+            column: slot.column + 1,
+            row: slot.row,
+        });
+        neighbours.push(Slot {
+            column: slot.column,
+            row: slot.row + 1,
+        });
+        neighbours
+            .into_iter()
+            .filter(|neighbour| self.is_free(neighbour))
+            .collect()
+    }
+
+    // Breadth first walking distance from `from` to every reachable slot
+    pub fn distances(&self, from: &Slot) -> HashMap<Slot, usize> {
+        let mut distances = HashMap::default();
+        let mut queue = VecDeque::new();
+        distances.insert(from.clone(), 0);
+        queue.push_back(from.clone());
+        while let Some(slot) = queue.pop_front() {
+            let distance = distances[&slot];
+            for neighbour in self.neighbours(&slot) {
+                if !distances.contains_key(&neighbour) {
+                    distances.insert(neighbour.clone(), distance + 1);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        distances
+    }
+
+    pub fn next_step(&self, from: &Slot, to: &Slot) -> Option<Slot> {
+        if from == to {
+            return None;
+        }
+        let distances = self.distances(to);
+        self.neighbours(from)
+            .into_iter()
+            .filter(|neighbour| distances.contains_key(neighbour))
+            .min_by_key(|neighbour| distances[neighbour])
+    }
+
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        let steps = (from.distance(to) / (TILE_SIZE / 4.)).ceil() as usize;
+        (0..=steps).all(|step| {
+            let point = from.lerp(to, step as f32 / steps.max(1) as f32);
+            self.is_free(&Slot {
+                column: ((point.x + TILE_SIZE / 2.) / TILE_SIZE) as usize,
+                row: ((point.y + TILE_SIZE / 2.) / TILE_SIZE) as usize,
+            })
+        })
+    }
+}
@@ -0,0 +1,227 @@
+use crate::audio::{BackgroundMusic, PauseBackground, ResumeBackground};
+use crate::loading::FontAssets;
+use crate::map::Map;
+use crate::menu::{spawn_panel_button, ButtonMaterials};
+use crate::save::LoadGame;
+use crate::scenes::SceneQueue;
+use crate::settings::{spawn_settings_panel, Settings, SettingsPanel};
+use crate::tween::Tween;
+use crate::{GameData, GameState};
+use bevy::prelude::*;
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PausedMusic>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(pause_game.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Paused).with_system(enter_pause.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused)
+                    .with_system(show_pause_menu.system())
+                    .with_system(resume_with_escape.system())
+                    .with_system(click_pause_button.system()),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Paused).with_system(exit_pause.system()));
+    }
+}
+
+#[derive(Default)]
+struct PausedMusic {
+    was_paused: bool,
+}
+
+struct PauseOverlay;
+
+#[derive(Clone, Copy, PartialEq)]
+enum PauseButton {
+    Resume,
+    RestartLevel,
+    Settings,
+    QuitToMenu,
+}
+
+fn pause_game(
+    game_state: Res<GameData>,
+    mut state: ResMut<State<GameState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Escape) || game_state.won {
+        return;
+    }
+    // the paused state runs in the same frame and would resume right away
+    keyboard_input.reset(KeyCode::Escape);
+    if let Err(error) = state.push(GameState::Paused) {
+        warn!("Failed to pause the game: {:?}", error);
+    }
+}
+
+fn enter_pause(
+    music: Res<BackgroundMusic>,
+    mut paused_music: ResMut<PausedMusic>,
+    mut pause_background: EventWriter<PauseBackground>,
+) {
+    // a scene might have paused the music already and should not have it resumed by us
+    paused_music.was_paused = music.paused;
+    pause_background.send(PauseBackground);
+}
+
+fn exit_pause(
+    mut commands: Commands,
+    paused_music: Res<PausedMusic>,
+    mut resume_background: EventWriter<ResumeBackground>,
+    overlays: Query<Entity, Or<(With<PauseOverlay>, With<SettingsPanel>)>>,
+) {
+    for entity in overlays.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if !paused_music.was_paused {
+        resume_background.send(ResumeBackground);
+    }
+}
+
+// Shown when entering the pause and again after the settings were closed
+fn show_pause_menu(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    overlays: Query<Entity, Or<(With<PauseOverlay>, With<SettingsPanel>)>>,
+) {
+    if overlays.iter().next().is_some() {
+        return;
+    }
+    let font = font_assets.fira_sans.clone();
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0., 0., 0., 0.6).into()),
+            ..Default::default()
+        })
+        .insert(PauseOverlay)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(10.)),
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "Paused",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 40.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+            for (button, text) in [
+                (PauseButton::Resume, "Resume"),
+                (PauseButton::RestartLevel, "Restart level"),
+                (PauseButton::Settings, "Settings"),
+                (PauseButton::QuitToMenu, "Quit to menu"),
+            ]
+            .iter()
+            {
+                spawn_panel_button(parent, &button_materials, &font, *button, 240., text);
+            }
+        });
+}
+
+fn resume_with_escape(
+    mut state: ResMut<State<GameState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    keyboard_input.reset(KeyCode::Escape);
+    resume(&mut state);
+}
+
+// A second click in the same frame finds the state change already queued
+fn resume(state: &mut State<GameState>) {
+    if let Err(error) = state.pop() {
+        warn!("Failed to resume the game: {:?}", error);
+    }
+}
+
+fn click_pause_button(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+    settings: Res<Settings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut state: ResMut<State<GameState>>,
+    mut game_state: ResMut<GameData>,
+    mut scene_queue: ResMut<SceneQueue>,
+    mut current_map: ResMut<Map>,
+    mut interaction_query: Query<
+        (&PauseButton, &Interaction, &mut Handle<ColorMaterial>),
+        Changed<Interaction>,
+    >,
+    overlays: Query<Entity, With<PauseOverlay>>,
+    tweens: Query<Entity, With<Tween>>,
+) {
+    for (button, interaction, mut material) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => match *button {
+                PauseButton::Resume => resume(&mut state),
+                PauseButton::RestartLevel => {
+                    game_state.scene = None;
+                    scene_queue.clear();
+                    game_state.frozen = false;
+                    // the player and camera survive the restart, their scene animations not
+                    for entity in tweens.iter() {
+                        commands.entity(entity).remove::<Tween>();
+                    }
+                    commands.remove_resource::<LoadGame>();
+                    let map = current_map.clone();
+                    *current_map = map;
+                    resume(&mut state);
+                }
+                PauseButton::Settings => {
+                    for overlay in overlays.iter() {
+                        commands.entity(overlay).despawn_recursive();
+                    }
+                    spawn_settings_panel(
+                        &mut commands,
+                        &font_assets,
+                        &button_materials,
+                        &mut materials,
+                        &settings,
+                    );
+                }
+                PauseButton::QuitToMenu => {
+                    // leaving the game removes the level
+                    game_state.scene = None;
+                    scene_queue.clear();
+                    game_state.frozen = false;
+                    game_state.won = false;
+                    commands.remove_resource::<LoadGame>();
+                    if let Err(error) = state.replace(GameState::Menu) {
+                        warn!("Failed to quit to the menu: {:?}", error);
+                    }
+                }
+            },
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
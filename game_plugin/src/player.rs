@@ -1,9 +1,12 @@
 use crate::actions::Actions;
 use crate::audio::BackgroundAudio;
+use crate::blocks::{Block, PushBlock};
+use crate::dash::{Stamina, DASH_SPEED_FACTOR};
+use crate::inventory::Inventory;
 use crate::loading::{AudioAssets, TextureAssets};
-use crate::map::{Collide, Dimensions, Map, MapSystemLabels, TILE_SIZE};
+use crate::map::{Collide, Dimensions, Map, MapSystemLabels, MapTile, Slot, TILE_SIZE};
 use crate::scenes::TriggerScene;
-use crate::{GameData, GameState};
+use crate::{GameData, GameState, TiledMap};
 use bevy::prelude::*;
 use std::f32::consts::PI;
 use std::ops::Deref;
@@ -14,6 +17,19 @@ pub struct Player;
 pub struct PlayerCamera;
 
 pub const PLAYER_Z: f32 = 5.;
+pub const SLOW_TILE_TINT: Color = Color::rgb(0.6, 0.45, 0.3);
+const SLOW_TILE_FACTOR: f32 = 0.5;
+
+// Mud and the like, halves the squirrel's speed
+pub struct SlowTile;
+
+// The squirrel moving one tile in the grid step control scheme
+pub struct GridStep {
+    from: Vec2,
+    to: Vec2,
+    timer: Timer,
+    queued: Option<Vec2>,
+}
 
 #[derive(SystemLabel, Clone, Hash, Debug, Eq, PartialEq)]
 pub enum PlayerSystemLabels {
@@ -31,6 +47,7 @@ impl Plugin for PlayerPlugin {
             SystemSet::on_update(GameState::Playing)
                 .with_system(reset_player_position.system())
                 .with_system(move_player.system().label(PlayerSystemLabels::MovePlayer))
+                .with_system(step_player.system().label(PlayerSystemLabels::MovePlayer))
                 .with_system(move_camera.system().after(PlayerSystemLabels::MovePlayer)),
         )
         .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(remove_player.system()));
@@ -54,59 +71,227 @@ fn spawn_player(
             )),
             ..Default::default()
         })
-        .insert(Player);
+        .insert(Player)
+        .insert(Inventory::default());
 }
 
 fn move_player(
     time: Res<Time>,
     game_state: Res<GameData>,
     actions: Res<Actions>,
+    stamina: Res<Stamina>,
     map: Res<Map>,
+    maps: Res<Assets<TiledMap>>,
     mut trigger_scene: EventWriter<TriggerScene>,
+    mut push_block: EventWriter<PushBlock>,
     mut player_query: Query<&mut Transform, (With<Player>, Without<PlayerCamera>)>,
-    collider_query: Query<&Collide>,
+    collider_query: Query<(Entity, &Collide, Option<&Block>)>,
+    slow_tiles: Query<&MapTile, With<SlowTile>>,
 ) {
     if actions.player_movement.is_none() || game_state.frozen {
         return;
     }
-    let speed = 250.;
-    let movement = Vec3::new(
-        actions.player_movement.unwrap().x * speed * time.delta_seconds(),
-        actions.player_movement.unwrap().y * speed * time.delta_seconds(),
-        0.,
-    );
-    let player_bounds = movement.normalize() * 8.;
     for mut player_transform in player_query.iter_mut() {
-        player_transform.rotation = Quat::from_rotation_z(
-            -1. * actions
-                .player_movement
-                .unwrap()
-                .angle_between(Vec2::new(0., 1.))
-                + PI,
+        let slot = map.slot_from_position(
+            player_transform.translation.x,
+            player_transform.translation.y,
         );
-        let x = ((player_transform.translation.x + movement.x + player_bounds.x + TILE_SIZE / 2.)
-            / TILE_SIZE) as usize;
-        let y = ((player_transform.translation.y + movement.y + player_bounds.y + TILE_SIZE / 2.)
-            / TILE_SIZE) as usize;
-        if x >= map.dimensions().columns || y >= map.dimensions().rows {
-            return;
-        }
-        for collide in collider_query.iter() {
-            if collide.x == x && collide.y == y {
+        let speed = player_speed(&stamina, &slow_tiles, &slot);
+        let movement = Vec3::new(
+            actions.player_movement.unwrap().x * speed * time.delta_seconds(),
+            actions.player_movement.unwrap().y * speed * time.delta_seconds(),
+            0.,
+        );
+        let player_bounds = movement.normalize() * 8.;
+        player_transform.rotation = facing(actions.player_movement.unwrap());
+        // move in small steps, so fast squirrels can't skip over walls
+        let steps = (movement.length() / (TILE_SIZE / 4.)).ceil().max(1.);
+        let step = movement / steps;
+        for _ in 0..steps as usize {
+            let x = ((player_transform.translation.x + step.x + player_bounds.x + TILE_SIZE / 2.)
+                / TILE_SIZE) as usize;
+            let y = ((player_transform.translation.y + step.y + player_bounds.y + TILE_SIZE / 2.)
+                / TILE_SIZE) as usize;
+            if x >= map.dimensions().columns || y >= map.dimensions().rows {
                 return;
             }
+            if blocked(
+                &map,
+                &player_transform,
+                Slot { column: x, row: y },
+                &collider_query,
+                &mut push_block,
+            ) {
+                return;
+            }
+            player_transform.translation += step;
+        }
+        // when the acorn flees, catching it is the way down
+        if !game_state.fleeing_acorn {
+            check_exits(&map, &maps, &player_transform, &mut trigger_scene);
+        }
+    }
+}
+
+fn step_player(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_state: Res<GameData>,
+    actions: Res<Actions>,
+    stamina: Res<Stamina>,
+    map: Res<Map>,
+    maps: Res<Assets<TiledMap>>,
+    mut trigger_scene: EventWriter<TriggerScene>,
+    mut push_block: EventWriter<PushBlock>,
+    mut player_query: Query<
+        (Entity, &mut Transform, Option<&mut GridStep>),
+        (With<Player>, Without<PlayerCamera>),
+    >,
+    collider_query: Query<(Entity, &Collide, Option<&Block>)>,
+    slow_tiles: Query<&MapTile, With<SlowTile>>,
+) {
+    let (entity, mut player_transform, grid_step) = match player_query.single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    // scenes and level changes move the squirrel themselves
+    if game_state.frozen || map.is_changed() {
+        if grid_step.is_some() {
+            commands.entity(entity).remove::<GridStep>();
+        }
+        return;
+    }
+    let mut direction = actions.player_step;
+    if let Some(mut grid_step) = grid_step {
+        if direction.is_some() {
+            grid_step.queued = direction;
         }
-        player_transform.translation += movement;
+        grid_step.timer.tick(time.delta());
+        let position = grid_step.from.lerp(grid_step.to, grid_step.timer.percent());
+        player_transform.translation.x = position.x;
+        player_transform.translation.y = position.y;
+        if !grid_step.timer.finished() {
+            return;
+        }
+        commands.entity(entity).remove::<GridStep>();
+        if !game_state.fleeing_acorn {
+            check_exits(&map, &maps, &player_transform, &mut trigger_scene);
+        }
+        direction = grid_step.queued;
+    }
+    let direction = match direction {
+        Some(direction) => direction,
+        None => return,
+    };
+    player_transform.rotation = facing(direction);
+    let slot = map.slot_from_position(
+        player_transform.translation.x,
+        player_transform.translation.y,
+    );
+    let column = slot.column as i32 + direction.x as i32;
+    let row = slot.row as i32 + direction.y as i32;
+    if column < 0
+        || row < 0
+        || column as usize >= map.dimensions().columns
+        || row as usize >= map.dimensions().rows
+    {
+        return;
+    }
+    let target = Slot {
+        column: column as usize,
+        row: row as usize,
+    };
+    if blocked(
+        &map,
+        &player_transform,
+        target.clone(),
+        &collider_query,
+        &mut push_block,
+    ) {
+        return;
+    }
+    let speed = player_speed(&stamina, &slow_tiles, &slot);
+    commands.entity(entity).insert(GridStep {
+        from: player_transform.translation.truncate(),
+        to: Vec2::new(
+            target.column as f32 * TILE_SIZE,
+            target.row as f32 * TILE_SIZE,
+        ),
+        timer: Timer::from_seconds(TILE_SIZE / speed, false),
+        queued: None,
+    });
+}
+
+fn player_speed(
+    stamina: &Stamina,
+    slow_tiles: &Query<&MapTile, With<SlowTile>>,
+    slot: &Slot,
+) -> f32 {
+    let speed = 250.;
+    if stamina.dashing() {
+        speed * DASH_SPEED_FACTOR
+    } else if slow_tiles
+        .iter()
+        .any(|tile| tile.column == slot.column && tile.row == slot.row)
+    {
+        speed * SLOW_TILE_FACTOR
+    } else {
+        speed
+    }
+}
+
+fn facing(direction: Vec2) -> Quat {
+    Quat::from_rotation_z(-1. * direction.angle_between(Vec2::new(0., 1.)) + PI)
+}
+
+// Is the slot taken? Blocks in the way get pushed.
+fn blocked(
+    map: &Map,
+    player_transform: &Transform,
+    slot: Slot,
+    collider_query: &Query<(Entity, &Collide, Option<&Block>)>,
+    push_block: &mut EventWriter<PushBlock>,
+) -> bool {
+    if let Some((entity, _collide, block)) = collider_query
+        .iter()
+        .find(|(_, collide, _)| collide.x == slot.column && collide.y == slot.row)
+    {
+        if block.is_some() {
+            push_block.send(PushBlock {
+                block: entity,
+                from: map.slot_from_position(
+                    player_transform.translation.x,
+                    player_transform.translation.y,
+                ),
+            });
+        }
+        return true;
+    }
+    false
+}
+
+fn check_exits(
+    map: &Map,
+    maps: &Assets<TiledMap>,
+    player_transform: &Transform,
+    trigger_scene: &mut EventWriter<TriggerScene>,
+) {
+    for exit in map.exits(maps) {
+        let exit_position = map.position_from_slot(exit.position.clone());
         if player_transform.translation.distance(Vec3::new(
-            map.goal_position().0,
-            map.goal_position().1,
+            exit_position.0,
+            exit_position.1,
             PLAYER_Z,
         )) < 25.
         {
-            if let Some(scene) = map.goal_scene((
-                player_transform.translation.x,
-                player_transform.translation.y,
-            )) {
+            if let Some(scene) = map.exit_scene(
+                &exit,
+                (
+                    player_transform.translation.x,
+                    player_transform.translation.y,
+                ),
+                None,
+            ) {
                 trigger_scene.send(TriggerScene { scene });
             }
         }
@@ -168,13 +353,16 @@ fn move_camera(
     game_state: Res<GameData>,
     actions: Res<Actions>,
     windows: Res<Windows>,
-    player_query: Query<&Transform, (With<Player>, Without<PlayerCamera>)>,
+    player_query: Query<(&Transform, Option<&GridStep>), (With<Player>, Without<PlayerCamera>)>,
     mut camera_query: Query<&mut Transform, (With<PlayerCamera>, Without<Player>)>,
 ) {
-    if actions.player_movement.is_none() || game_state.frozen {
+    if game_state.frozen {
         return;
     }
-    if let Ok(player_transform) = player_query.single() {
+    if let Ok((player_transform, grid_step)) = player_query.single() {
+        if actions.player_movement.is_none() && grid_step.is_none() {
+            return;
+        }
         let window = windows.get_primary().expect("No primary window");
         let (x, y) = calc_camera_position(
             player_transform.translation.x,
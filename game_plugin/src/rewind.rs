@@ -0,0 +1,132 @@
+use crate::actions::Actions;
+use crate::map::{Map, MapSystemLabels};
+use crate::player::{GridStep, Player, PlayerSystemLabels};
+use crate::scenes::CutScene;
+use crate::score::{Collectible, LevelScore};
+use crate::wiring::{RollBackSwitches, Switch};
+use crate::{GameData, GameState};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use std::collections::VecDeque;
+
+// about five seconds at 60 fps
+const MAX_SNAPSHOTS: usize = 300;
+
+pub struct RewindPlugin;
+
+impl Plugin for RewindPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Rewind>().add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .after(MapSystemLabels::DrawMap)
+                .with_system(reset_rewind.system())
+                .with_system(rewind.system().after(PlayerSystemLabels::MovePlayer)),
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct Rewind {
+    pub budget: Duration,
+    pub rewinding: bool,
+    snapshots: VecDeque<Snapshot>,
+}
+
+struct Snapshot {
+    player: Transform,
+    signals: Vec<bool>,
+    collectibles: Vec<(Entity, bool)>,
+    time: Duration,
+}
+
+impl Rewind {
+    // Drop the recorded frames, e.g. after the squirrel was moved by loading a save
+    pub fn forget(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+fn reset_rewind(current_map: Res<Map>, mut rewind: ResMut<Rewind>) {
+    if !current_map.is_changed() {
+        return;
+    }
+    rewind.budget = current_map.rewind_budget();
+    rewind.rewinding = false;
+    rewind.forget();
+}
+
+fn rewind(
+    mut commands: Commands,
+    time: Res<Time>,
+    actions: Res<Actions>,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    mut rewind: ResMut<Rewind>,
+    mut score: ResMut<LevelScore>,
+    mut roll_back_switches: EventWriter<RollBackSwitches>,
+    mut player_query: Query<(Entity, &mut Transform), With<Player>>,
+    mut collectibles: Query<(Entity, &mut Collectible, &mut Visible)>,
+    switches: Query<&Switch>,
+) {
+    if let Some(scene) = &game_state.scene {
+        // dying can't be undone
+        if let CutScene::Died { .. } = scene {
+            rewind.snapshots.clear();
+        }
+        rewind.rewinding = false;
+        return;
+    }
+    if game_state.frozen {
+        return;
+    }
+    let (player, mut player_transform) = match player_query.single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let mut signals = vec![false; current_map.active_elements().len()];
+    for switch in switches.iter() {
+        if let Some(signal) = signals.get_mut(switch.index) {
+            *signal = switch.active;
+        }
+    }
+
+    if actions.rewind && rewind.budget > Duration::default() {
+        if let Some(snapshot) = rewind.snapshots.pop_back() {
+            rewind.rewinding = true;
+            rewind.budget = rewind.budget.saturating_sub(time.delta());
+            *player_transform = snapshot.player;
+            commands.entity(player).remove::<GridStep>();
+            if snapshot.signals != signals {
+                roll_back_switches.send(RollBackSwitches {
+                    signals: snapshot.signals,
+                });
+            }
+            for (entity, collected) in snapshot.collectibles {
+                if let Ok((_, mut collectible, mut visible)) = collectibles.get_mut(entity) {
+                    collectible.collected = collected;
+                    visible.is_visible = !collected;
+                }
+            }
+            score.collected = collectibles
+                .iter_mut()
+                .filter(|(_, collectible, _)| collectible.collected)
+                .count();
+            score.time = snapshot.time;
+            return;
+        }
+    }
+    rewind.rewinding = false;
+
+    if rewind.snapshots.len() >= MAX_SNAPSHOTS {
+        rewind.snapshots.pop_front();
+    }
+    rewind.snapshots.push_back(Snapshot {
+        player: *player_transform,
+        signals,
+        collectibles: collectibles
+            .iter_mut()
+            .map(|(entity, collectible, _)| (entity, collectible.collected))
+            .collect(),
+        time: score.time,
+    });
+}
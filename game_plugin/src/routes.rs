@@ -0,0 +1,83 @@
+use crate::map::Map;
+use crate::ui::WonEvent;
+use crate::{GameState, TiledMap};
+use bevy::prelude::*;
+
+pub struct RoutesPlugin;
+
+impl Plugin for RoutesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Route>()
+            .init_resource::<ExploredRoutes>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(track_route.system())
+                    .with_system(explore_route.system()),
+            );
+    }
+}
+
+pub struct Route {
+    pub maps: Vec<Map>,
+}
+
+impl Default for Route {
+    fn default() -> Self {
+        Route {
+            maps: vec![Map::Ground],
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ExploredRoutes {
+    pub routes: Vec<Vec<Map>>,
+}
+
+pub fn route_name(maps: &[Map]) -> String {
+    maps.iter()
+        .map(|map| map.name())
+        .collect::<Vec<&str>>()
+        .join(" > ")
+}
+
+// Every path through the exits from the surface down to the final level
+pub fn all_routes(maps: &Assets<TiledMap>) -> Vec<Vec<Map>> {
+    let mut routes = vec![];
+    let mut open = vec![vec![Map::Ground]];
+    while let Some(route) = open.pop() {
+        let exits = route.last().unwrap().exits(maps);
+        for exit in exits.iter().rev() {
+            match &exit.to {
+                Some(to) => {
+                    let mut next = route.clone();
+                    next.push(to.clone());
+                    open.push(next);
+                }
+                None => routes.push(route.clone()),
+            }
+        }
+    }
+    routes
+}
+
+fn track_route(current_map: Res<Map>, mut route: ResMut<Route>) {
+    if !current_map.is_changed() {
+        return;
+    }
+    if *current_map == Map::Ground {
+        route.maps = vec![Map::Ground];
+    } else if route.maps.last() != Some(&current_map) {
+        route.maps.push(current_map.clone());
+    }
+}
+
+fn explore_route(
+    mut won_events: EventReader<WonEvent>,
+    route: Res<Route>,
+    mut explored: ResMut<ExploredRoutes>,
+) {
+    if won_events.iter().last().is_some() && !explored.routes.contains(&route.maps) {
+        explored.routes.push(route.maps.clone());
+    }
+}
@@ -0,0 +1,545 @@
+use crate::blocks::{Block, RestoreBlocks};
+use crate::digging::{Digs, RestoreDigs};
+use crate::inventory::{Inventory, Item, Key, LockedDoor, RestoreInventory};
+use crate::lava::{LavaFlow, RestoreLava};
+use crate::map::{Collide, Map, MapSystemLabels, Slot};
+use crate::player::{calc_camera_position, GridStep, Player, PlayerCamera};
+use crate::rewind::Rewind;
+use crate::routes::Route;
+use crate::score::{BestScores, Collectible, LevelResult, LevelScore, Playtime};
+use crate::skip::SeenScenes;
+use crate::storage::GameStorage;
+use crate::wiring::{RollBackSwitches, Switch};
+use crate::{GameData, GameState, TiledMap};
+use bevy::app::AppExit;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::utils::{Duration, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+#[cfg(not(feature = "web"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SAVE_VERSION: u32 = 3;
+pub const SAVE_SLOTS: usize = 3;
+// the single save file from before there were slots
+const LEGACY_SAVE_KEY: &str = "save";
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SaveSlots>()
+            .insert_resource(Autosave {
+                timer: Timer::new(AUTOSAVE_INTERVAL, true),
+            })
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .after(MapSystemLabels::DrawMap)
+                    .with_system(autosave.system())
+                    .with_system(restore_save.system()),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(autosave.system()));
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SaveGame {
+    pub version: u32,
+    pub level: Map,
+    pub route: Vec<Map>,
+    pub fleeing_acorn: bool,
+    pub signals: Vec<bool>,
+    pub player: (f32, f32),
+    pub collected: Vec<Slot>,
+    // seconds spent in the current level
+    pub time: f32,
+    // seconds spent on this save
+    pub total_time: f32,
+    pub best: HashMap<String, LevelResult>,
+    // seconds since the unix epoch
+    pub last_played: u64,
+    #[serde(default)]
+    pub seen_scenes: HashSet<String>,
+    pub items: Vec<Item>,
+    // bevy slots of the keys picked up and the doors opened in the current level
+    pub picked_keys: Vec<Slot>,
+    pub unlocked_doors: Vec<Slot>,
+    // pushed blocks as (home, slot)
+    pub moved_blocks: Vec<(Slot, Slot)>,
+    pub dug_walls: Vec<Slot>,
+    pub digs_left: usize,
+    // seconds of rewinding left in the current level
+    pub rewind_budget: f32,
+    pub flooded: Vec<Slot>,
+}
+
+impl SaveGame {
+    pub fn stars(&self) -> u32 {
+        self.best.values().map(|best| best.stars as u32).sum()
+    }
+}
+
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+// Version 1, a single save without summary
+#[derive(Deserialize)]
+struct SaveGameV1 {
+    level: Map,
+    route: Vec<Map>,
+    fleeing_acorn: bool,
+    signals: Vec<bool>,
+    player: (f32, f32),
+    collected: Vec<Slot>,
+    time: f32,
+}
+
+impl From<SaveGameV1> for SaveGameV2 {
+    fn from(save: SaveGameV1) -> Self {
+        SaveGameV2 {
+            level: save.level,
+            route: save.route,
+            fleeing_acorn: save.fleeing_acorn,
+            signals: save.signals,
+            player: save.player,
+            collected: save.collected,
+            time: save.time,
+            total_time: save.time,
+            best: HashMap::default(),
+            last_played: 0,
+            seen_scenes: HashSet::default(),
+        }
+    }
+}
+
+// Version 2, without the state of keys, blocks, digging, rewinding and lava
+#[derive(Deserialize)]
+struct SaveGameV2 {
+    level: Map,
+    route: Vec<Map>,
+    fleeing_acorn: bool,
+    signals: Vec<bool>,
+    player: (f32, f32),
+    collected: Vec<Slot>,
+    time: f32,
+    total_time: f32,
+    best: HashMap<String, LevelResult>,
+    last_played: u64,
+    #[serde(default)]
+    seen_scenes: HashSet<String>,
+}
+
+// The level keeps the state it starts with
+impl From<SaveGameV2> for SaveGame {
+    fn from(save: SaveGameV2) -> Self {
+        SaveGame {
+            version: SAVE_VERSION,
+            digs_left: save.level.digs(),
+            rewind_budget: save.level.rewind_budget().as_secs_f32(),
+            level: save.level,
+            route: save.route,
+            fleeing_acorn: save.fleeing_acorn,
+            signals: save.signals,
+            player: save.player,
+            collected: save.collected,
+            time: save.time,
+            total_time: save.total_time,
+            best: save.best,
+            last_played: save.last_played,
+            seen_scenes: save.seen_scenes,
+            items: vec![],
+            picked_keys: vec![],
+            unlocked_doors: vec![],
+            moved_blocks: vec![],
+            dug_walls: vec![],
+            flooded: vec![],
+        }
+    }
+}
+
+// Every older version is read with its own struct and migrated step by step
+fn parse_save(content: &str) -> Result<SaveGame, String> {
+    let header: SaveHeader = ron::de::from_str(content).map_err(|error| error.to_string())?;
+    match header.version {
+        1 => ron::de::from_str::<SaveGameV1>(content)
+            .map(|save| SaveGame::from(SaveGameV2::from(save)))
+            .map_err(|error| error.to_string()),
+        2 => ron::de::from_str::<SaveGameV2>(content)
+            .map(SaveGame::from)
+            .map_err(|error| error.to_string()),
+        SAVE_VERSION => ron::de::from_str(content).map_err(|error| error.to_string()),
+        version => Err(format!("unknown version {}", version)),
+    }
+}
+
+pub struct SaveSlots {
+    pub slots: Vec<Option<SaveGame>>,
+    // the slot the running game is saved to
+    pub active: Option<usize>,
+}
+
+impl FromWorld for SaveSlots {
+    fn from_world(world: &mut World) -> Self {
+        let storage = world
+            .get_resource::<GameStorage>()
+            .expect("The storage is needed to read the save slots");
+        let mut slots: Vec<Option<SaveGame>> = (0..SAVE_SLOTS)
+            .map(|slot| read_save(storage, &slot_key(slot)))
+            .collect();
+        if slots[0].is_none() {
+            slots[0] = read_save(storage, LEGACY_SAVE_KEY);
+        }
+        SaveSlots {
+            slots,
+            active: None,
+        }
+    }
+}
+
+impl SaveSlots {
+    // The slot that was played last, for "Continue"
+    pub fn latest(&self) -> Option<usize> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, save)| save.as_ref().map(|save| (index, save.last_played)))
+            .max_by_key(|(_, last_played)| *last_played)
+            .map(|(index, _)| index)
+    }
+
+    pub fn delete(&mut self, storage: &mut GameStorage, slot: usize) {
+        self.slots[slot] = None;
+        if self.active == Some(slot) {
+            self.active = None;
+        }
+        storage.remove(&slot_key(slot));
+        if slot == 0 {
+            storage.remove(LEGACY_SAVE_KEY);
+        }
+    }
+}
+
+// A save waiting to be applied once its level is drawn
+pub struct LoadGame {
+    save: SaveGame,
+    frames: u8,
+}
+
+impl LoadGame {
+    pub fn new(save: SaveGame) -> Self {
+        LoadGame { save, frames: 0 }
+    }
+}
+
+struct Autosave {
+    timer: Timer,
+}
+
+#[cfg(not(feature = "web"))]
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+// the system clock is not available in the browser
+#[cfg(feature = "web")]
+pub fn now() -> u64 {
+    (js_sys::Date::now() / 1000.) as u64
+}
+
+fn slot_key(slot: usize) -> String {
+    format!("slot_{}", slot + 1)
+}
+
+fn read_save(storage: &GameStorage, key: &str) -> Option<SaveGame> {
+    let content = storage.read(key)?;
+    match parse_save(&content) {
+        Ok(save) => Some(save),
+        Err(error) => {
+            warn!("Ignoring save '{}': {}", key, error);
+            None
+        }
+    }
+}
+
+fn write_save(storage: &mut GameStorage, slot: usize, save: &SaveGame) {
+    match ron::ser::to_string_pretty(save, Default::default()) {
+        Ok(content) => storage.write(&slot_key(slot), &content),
+        Err(error) => warn!("Failed to serialize the game: {}", error),
+    }
+}
+
+// Everything the save keeps beyond the running level
+#[derive(SystemParam)]
+pub struct Progress<'a> {
+    playtime: Res<'a, Playtime>,
+    best_scores: Res<'a, BestScores>,
+    seen_scenes: Res<'a, SeenScenes>,
+}
+
+// The running level beyond switches and collectibles
+#[derive(SystemParam)]
+pub struct LevelState<'a> {
+    digs: Res<'a, Digs>,
+    rewind: Res<'a, Rewind>,
+    lava: Res<'a, LavaFlow>,
+    inventory: Query<'a, &'static Inventory, With<Player>>,
+    keys: Query<'a, &'static Key>,
+    doors: Query<'a, &'static Collide, With<LockedDoor>>,
+    blocks: Query<'a, &'static Block>,
+    // where the keys and doors lie before anything was picked up or unlocked
+    maps: Res<'a, Assets<TiledMap>>,
+}
+
+// Events for the modules restoring their part of a loaded level
+#[derive(SystemParam)]
+pub struct LevelRestore<'a> {
+    switches: EventWriter<'a, RollBackSwitches>,
+    inventory: EventWriter<'a, RestoreInventory>,
+    blocks: EventWriter<'a, RestoreBlocks>,
+    digs: EventWriter<'a, RestoreDigs>,
+    lava: EventWriter<'a, RestoreLava>,
+}
+
+fn autosave(
+    time: Res<Time>,
+    state: Res<State<GameState>>,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    route: Res<Route>,
+    score: Res<LevelScore>,
+    progress: Progress,
+    level: LevelState,
+    load: Option<Res<LoadGame>>,
+    mut autosave: ResMut<Autosave>,
+    mut save_slots: ResMut<SaveSlots>,
+    mut storage: ResMut<GameStorage>,
+    mut exit: EventReader<AppExit>,
+    player_query: Query<&Transform, With<Player>>,
+    switches: Query<&Switch>,
+    collectibles: Query<&Collectible>,
+) {
+    autosave.timer.tick(time.delta());
+    let exiting = exit.iter().last().is_some();
+    // saving when pausing keeps the progress if the game is quit from the pause menu
+    let pausing = *state.current() == GameState::Paused;
+    if !autosave.timer.just_finished() && !exiting && !pausing {
+        return;
+    }
+    let slot = match save_slots.active {
+        Some(slot) => slot,
+        None => return,
+    };
+    // mid scene or still loading, the world is not in a state worth keeping
+    if game_state.scene.is_some() || game_state.won || load.is_some() {
+        return;
+    }
+    let player_transform = match player_query.single() {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+    let mut signals = vec![false; current_map.active_elements().len()];
+    for switch in switches.iter() {
+        if let Some(signal) = signals.get_mut(switch.index) {
+            *signal = switch.active;
+        }
+    }
+    let keys: Vec<Slot> = level.keys.iter().map(|key| key.slot.clone()).collect();
+    let doors: Vec<Slot> = level
+        .doors
+        .iter()
+        .map(|door| Slot {
+            column: door.x,
+            row: door.y,
+        })
+        .collect();
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        level: current_map.clone(),
+        route: route.maps.clone(),
+        fleeing_acorn: game_state.fleeing_acorn,
+        signals,
+        player: (
+            player_transform.translation.x,
+            player_transform.translation.y,
+        ),
+        collected: collectibles
+            .iter()
+            .filter(|collectible| collectible.collected)
+            .map(|collectible| collectible.slot.clone())
+            .collect(),
+        time: score.time.as_secs_f32(),
+        total_time: progress.playtime.total.as_secs_f32(),
+        best: progress.best_scores.levels.clone(),
+        last_played: now(),
+        seen_scenes: progress.seen_scenes.scenes.clone(),
+        items: level
+            .inventory
+            .single()
+            .map(|inventory| inventory.items.clone())
+            .unwrap_or_default(),
+        picked_keys: current_map
+            .keys(&level.maps)
+            .into_iter()
+            .map(|key| current_map.tiled_slot_to_bevy_slot(key.position))
+            .filter(|slot| !keys.contains(slot))
+            .collect(),
+        unlocked_doors: current_map
+            .locked_doors(&level.maps)
+            .into_iter()
+            .map(|door| current_map.tiled_slot_to_bevy_slot(door.position))
+            .filter(|slot| !doors.contains(slot))
+            .collect(),
+        moved_blocks: level
+            .blocks
+            .iter()
+            .filter(|block| block.slot != block.home)
+            .map(|block| (block.home.clone(), block.slot.clone()))
+            .collect(),
+        dug_walls: level.digs.dug.clone(),
+        digs_left: level.digs.left,
+        rewind_budget: level.rewind.budget.as_secs_f32(),
+        flooded: level.lava.flooded(),
+    };
+    write_save(&mut storage, slot, &save);
+    save_slots.slots[slot] = Some(save);
+}
+
+fn restore_save(
+    mut commands: Commands,
+    current_map: Res<Map>,
+    game_state: Res<GameData>,
+    windows: Res<Windows>,
+    load: Option<ResMut<LoadGame>>,
+    mut score: ResMut<LevelScore>,
+    mut route: ResMut<Route>,
+    mut rewind: ResMut<Rewind>,
+    mut restore: LevelRestore,
+    mut player_query: Query<(Entity, &mut Transform), (With<Player>, Without<PlayerCamera>)>,
+    mut camera_query: Query<&mut Transform, (With<PlayerCamera>, Without<Player>)>,
+    mut collectibles: Query<(&mut Collectible, &mut Visible)>,
+) {
+    let mut load = match load {
+        Some(load) => load,
+        None => return,
+    };
+    if current_map.is_changed() {
+        load.frames = 0;
+        return;
+    }
+    // the intro is triggered while drawing the level and starts a frame later
+    if load.frames < 2 {
+        load.frames += 1;
+        return;
+    }
+    if game_state.scene.is_some() {
+        return;
+    }
+    let save = &load.save;
+    if let Ok((player, mut player_transform)) = player_query.single_mut() {
+        player_transform.translation.x = save.player.0;
+        player_transform.translation.y = save.player.1;
+        commands.entity(player).remove::<GridStep>();
+    }
+    if let Ok(mut camera_transform) = camera_query.single_mut() {
+        let window = windows.get_primary().expect("No primary window");
+        let (x, y) = calc_camera_position(
+            save.player.0,
+            save.player.1,
+            window,
+            &current_map.dimensions(),
+        );
+        camera_transform.translation.x = x;
+        camera_transform.translation.y = y;
+    }
+    restore.switches.send(RollBackSwitches {
+        signals: save.signals.clone(),
+    });
+    restore.inventory.send(RestoreInventory {
+        items: save.items.clone(),
+        picked_keys: save.picked_keys.clone(),
+        unlocked_doors: save.unlocked_doors.clone(),
+    });
+    restore.blocks.send(RestoreBlocks {
+        moved: save.moved_blocks.clone(),
+    });
+    restore.digs.send(RestoreDigs {
+        left: save.digs_left,
+        dug: save.dug_walls.clone(),
+    });
+    restore.lava.send(RestoreLava {
+        flooded: save.flooded.clone(),
+    });
+    for (mut collectible, mut visible) in collectibles.iter_mut() {
+        collectible.collected = save.collected.contains(&collectible.slot);
+        visible.is_visible = !collectible.collected;
+    }
+    score.collected = save.collected.len();
+    score.time = Duration::from_secs_f32(save.time);
+    route.maps = save.route.clone();
+    rewind.budget = Duration::from_secs_f32(save.rewind_budget);
+    rewind.forget();
+    commands.remove_resource::<LoadGame>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAVE_V1: &str = "(
+        version: 1,
+        level: Dirt,
+        route: [Ground],
+        fleeing_acorn: true,
+        signals: [true, false],
+        player: (64., 128.),
+        collected: [(column: 3, row: 3)],
+        time: 12.5,
+    )";
+
+    #[test]
+    fn migrates_version_1() {
+        let save = parse_save(SAVE_V1).expect("a version 1 save should be migrated");
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.level, Map::Dirt);
+        assert_eq!(save.route, vec![Map::Ground]);
+        assert!(save.fleeing_acorn);
+        assert_eq!(save.signals, vec![true, false]);
+        assert_eq!(save.player, (64., 128.));
+        assert_eq!(save.collected, vec![Slot { column: 3, row: 3 }]);
+        assert_eq!(save.time, 12.5);
+        // the level time was all there was to the save
+        assert_eq!(save.total_time, 12.5);
+        assert!(save.best.is_empty());
+        assert!(save.seen_scenes.is_empty());
+        // the level state starts fresh
+        assert_eq!(save.digs_left, Map::Dirt.digs());
+        assert_eq!(save.rewind_budget, Map::Dirt.rewind_budget().as_secs_f32());
+        assert!(save.items.is_empty());
+        assert!(save.moved_blocks.is_empty());
+        assert!(save.dug_walls.is_empty());
+    }
+
+    #[test]
+    fn reads_the_current_version() {
+        let save = parse_save(SAVE_V1).unwrap();
+        let content = ron::ser::to_string(&save).unwrap();
+        let read = parse_save(&content).expect("a current save should be read");
+        assert_eq!(read.level, save.level);
+        assert_eq!(read.collected, save.collected);
+        assert_eq!(read.digs_left, save.digs_left);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let error = parse_save("(version: 99)").err();
+        assert_eq!(error, Some("unknown version 99".to_owned()));
+        assert!(parse_save("not a save").is_err());
+    }
+}
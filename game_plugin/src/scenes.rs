@@ -1,13 +1,23 @@
+use crate::acorn::release_acorn;
 use crate::actions::Actions;
 use crate::audio::{AudioEffect, BackgroundAudio, PauseBackground, StopAudioEffects};
+use crate::enemies::Enemy;
 use crate::loading::{AudioAssets, TextureAssets};
-use crate::map::{Acorn, ButtonWall, Collide, Map};
+use crate::map::{Acorn, Map};
 use crate::player::{Player, PlayerCamera};
+use crate::score::LevelFinished;
+use crate::skip::SkipSystemLabels;
+use crate::timeline::{
+    Anchor, Cue, Motion, Point, SceneEvent, Sound, SwapTarget, Target, Timeline,
+};
+use crate::tween::{Easing, Tween, TweenProperty, TweenSystemLabels};
 use crate::ui::WonEvent;
+use crate::wiring::{apply_wall_state, RollBackSwitches, Wall, WiringSystemLabels};
 use crate::{GameData, GameState};
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
-use std::f32::consts::PI;
-use std::ops::Deref;
+use bevy_kira_audio::AudioSource;
+use std::cmp::Reverse;
 use std::time::Duration;
 
 pub struct ScenesPlugin;
@@ -16,7 +26,7 @@ pub struct TriggerScene {
     pub scene: CutScene,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum CutScene {
     Intro {
         camera_from: (f32, f32),
@@ -24,8 +34,8 @@ pub enum CutScene {
         acorn_falls: bool,
     },
     ActivateButton {
-        button: Entity,
-        wall: Entity,
+        // the switched walls and whether they open
+        walls: Vec<(Entity, bool)>,
         camera_from: (f32, f32),
         camera_to: (f32, f32),
     },
@@ -34,449 +44,700 @@ pub enum CutScene {
         camera_to: (f32, f32),
         to: Map,
     },
-    Won,
+    Caught {
+        player_from: (f32, f32),
+        camera_from: (f32, f32),
+        camera_to: (f32, f32),
+    },
+    Died {
+        player_from: (f32, f32),
+        respawn: (f32, f32),
+        camera_from: (f32, f32),
+        camera_to: (f32, f32),
+        restart_level: bool,
+        signals: Option<Vec<bool>>,
+    },
+    Won {
+        acorn: (f32, f32),
+    },
+}
+
+impl CutScene {
+    // Caught and died are still animated in code
+    pub fn timeline(&self, map: &Map) -> Option<String> {
+        match self {
+            // only the acorn in the lava level refuses to jump
+            CutScene::Intro { .. } if *map == Map::Lava => {
+                Some("cutscenes/intro_lava.timeline".to_owned())
+            }
+            CutScene::Intro { .. } => Some("cutscenes/intro.timeline".to_owned()),
+            CutScene::ActivateButton { .. } => {
+                Some("cutscenes/activate_button.timeline".to_owned())
+            }
+            CutScene::MapTransition { .. } => Some("cutscenes/map_transition.timeline".to_owned()),
+            CutScene::Won { .. } => Some("cutscenes/won.timeline".to_owned()),
+            CutScene::Caught { .. } | CutScene::Died { .. } => None,
+        }
+    }
+
+    // Identifies a scene across sessions, so it can be skipped once it was seen on a save.
+    // Getting caught or dying is gameplay and never counts.
+    pub fn seen_key(&self, map: &Map) -> Option<String> {
+        match self {
+            CutScene::Intro { .. } => Some(format!("intro_{}", map.name())),
+            CutScene::ActivateButton { camera_to, .. } => Some(format!(
+                "button_{}_{}_{}",
+                map.name(),
+                camera_to.0 as i32,
+                camera_to.1 as i32
+            )),
+            CutScene::MapTransition { .. } => Some(format!("transition_{}", map.name())),
+            CutScene::Won { .. } => Some("won".to_owned()),
+            CutScene::Caught { .. } | CutScene::Died { .. } => None,
+        }
+    }
+
+    // Waiting scenes with a higher priority play first. Leaving the level comes last, so
+    // everything else that happened in it still gets shown.
+    fn priority(&self) -> u8 {
+        match self {
+            CutScene::Caught { .. } | CutScene::Died { .. } => 3,
+            CutScene::Intro { .. } => 2,
+            CutScene::ActivateButton { .. } => 1,
+            CutScene::MapTransition { .. } | CutScene::Won { .. } => 0,
+        }
+    }
+
+    // the squirrel gets sent back, so anything else ending the attempt comes too late
+    fn ends_attempt(&self) -> bool {
+        matches!(self, CutScene::Caught { .. } | CutScene::Died { .. })
+    }
+
+    fn leaves_level(&self) -> bool {
+        matches!(self, CutScene::MapTransition { .. } | CutScene::Won { .. })
+    }
+
+    fn camera_path(&self) -> Option<((f32, f32), (f32, f32))> {
+        match self {
+            CutScene::Intro {
+                camera_from,
+                camera_to,
+                ..
+            }
+            | CutScene::ActivateButton {
+                camera_from,
+                camera_to,
+                ..
+            }
+            | CutScene::MapTransition {
+                camera_from,
+                camera_to,
+                ..
+            }
+            | CutScene::Caught {
+                camera_from,
+                camera_to,
+                ..
+            }
+            | CutScene::Died {
+                camera_from,
+                camera_to,
+                ..
+            } => Some((*camera_from, *camera_to)),
+            CutScene::Won { .. } => None,
+        }
+    }
 }
 
 impl Plugin for ScenesPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_event::<TriggerScene>().add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .with_system(run_intro.system())
-                .with_system(run_transition_scene.system())
-                .with_system(run_activate_button_scene.system())
-                .with_system(run_won_scene.system())
-                .with_system(trigger_scene.system()),
-        );
+        app.add_event::<TriggerScene>()
+            .init_resource::<SceneQueue>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(advance_scene_clock.system().label(SceneSystemLabels::Clock)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .after(SceneSystemLabels::Clock)
+                    .after(TweenSystemLabels::Animate)
+                    .after(SkipSystemLabels::Hold)
+                    .label(SceneSystemLabels::Run)
+                    .with_system(run_timeline.system())
+                    .with_system(run_caught_scene.system())
+                    .with_system(run_died_scene.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .after(SceneSystemLabels::Run)
+                    // a button scene has to freeze the game before the walls are evaluated again
+                    .after(WiringSystemLabels::Walls)
+                    .with_system(trigger_scene.system()),
+            );
     }
 }
 
-fn run_intro(
+#[derive(SystemLabel, Clone, Hash, Debug, Eq, PartialEq)]
+enum SceneSystemLabels {
+    Clock,
+    Run,
+}
+
+// Scenes triggered while another one is playing
+#[derive(Default)]
+pub struct SceneQueue {
+    scenes: Vec<CutScene>,
+}
+
+impl SceneQueue {
+    // Scenes of a level that is left or restarted are dropped
+    pub fn clear(&mut self) {
+        self.scenes.clear();
+    }
+
+    // Triggers repeat while their cause lasts, e.g. an enemy touching the squirrel every frame
+    fn push(&mut self, scene: CutScene, active: Option<&CutScene>) {
+        let attempt_ended = active
+            .into_iter()
+            .chain(self.scenes.iter())
+            .any(CutScene::ends_attempt);
+        if scene.ends_attempt() {
+            if attempt_ended {
+                return;
+            }
+            // a squirrel sent back doesn't reach the exit it was about to take
+            self.scenes.retain(|queued| !queued.leaves_level());
+        } else if scene.leaves_level() && attempt_ended {
+            return;
+        }
+        if self.scenes.contains(&scene) {
+            return;
+        }
+        self.scenes.push(scene);
+    }
+
+    // The waiting scene with the highest priority; the earlier one for equal priorities
+    fn pop(&mut self) -> Option<CutScene> {
+        let next = self
+            .scenes
+            .iter()
+            .enumerate()
+            .max_by_key(|(index, scene)| (scene.priority(), Reverse(*index)))
+            .map(|(index, _)| index)?;
+        Some(self.scenes.remove(next))
+    }
+}
+
+// a stalled frame, e.g. while dragging the window, should not skip half a scene
+const MAX_SCENE_FRAME: Duration = Duration::from_millis(100);
+
+fn advance_scene_clock(time: Res<Time>, mut game_state: ResMut<GameData>) {
+    if game_state.scene.is_some() {
+        game_state.scene_time += time.delta().min(MAX_SCENE_FRAME);
+    }
+}
+
+#[derive(SystemParam)]
+pub struct SceneEvents<'a> {
+    audio_effect: EventWriter<'a, AudioEffect>,
+    stop_audio_effects: EventWriter<'a, StopAudioEffects>,
+    pause_background: EventWriter<'a, PauseBackground>,
+    background_audio: EventWriter<'a, BackgroundAudio>,
+    won: EventWriter<'a, WonEvent>,
+    level_finished: EventWriter<'a, LevelFinished>,
+}
+
+// Plays the timeline of the active scene and applies its end state when done or skipped
+fn run_timeline(
     mut commands: Commands,
     mut game_state: ResMut<GameData>,
-    audio_assets: Res<AudioAssets>,
-    mut audio_effect: EventWriter<AudioEffect>,
-    current_map: Res<Map>,
     actions: Res<Actions>,
-    time: Res<Time>,
+    mut current_map: ResMut<Map>,
+    asset_server: Res<AssetServer>,
+    timelines: Res<Assets<Timeline>>,
+    audio_assets: Res<AudioAssets>,
+    textures: Res<TextureAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut events: SceneEvents,
+    player: Query<Entity, With<Player>>,
     mut acorn: Query<(Entity, &mut Transform), (With<Acorn>, Without<PlayerCamera>)>,
-    mut camera: Query<&mut Transform, (With<PlayerCamera>, Without<Acorn>)>,
+    mut camera: Query<(Entity, &mut Transform), (With<PlayerCamera>, Without<Acorn>)>,
+    mut walls: Query<(&mut Wall, &mut Handle<ColorMaterial>)>,
+    mut queue: ResMut<SceneQueue>,
 ) {
-    if let Some(scene) = game_state.scene.clone() {
-        if let CutScene::Intro {
-            camera_from,
-            camera_to,
-            acorn_falls,
-        } = scene
-        {
-            if actions.scip_scene {
-                if acorn_falls {
-                    if let Ok((acorn, _acorn_transform)) = acorn.single_mut() {
-                        commands.entity(acorn).despawn();
-                    }
-                } else {
-                    let goal = current_map.goal_position();
-                    if let Ok((_acorn, mut acorn_transform)) = acorn.single_mut() {
-                        acorn_transform.translation.x = goal.0;
-                        acorn_transform.translation.y = goal.1;
-                    }
-                }
-                if let Ok(mut transform) = camera.single_mut() {
-                    transform.translation.x = camera_from.0;
-                    transform.translation.y = camera_from.1;
+    let scene = match game_state.scene.clone() {
+        Some(scene) => scene,
+        None => return,
+    };
+    let timeline = match scene
+        .timeline(&current_map)
+        .and_then(|path| timelines.get(asset_server.get_handle::<Timeline, _>(path.as_str())))
+    {
+        Some(timeline) => timeline,
+        None => return,
+    };
+    let skipped = actions.scip_scene;
+    let elapsed = game_state.scene_time.as_secs_f32();
+    let acorn_position = match scene {
+        CutScene::Won { acorn } => acorn,
+        _ => acorn
+            .single_mut()
+            .map(|(_, transform)| (transform.translation.x, transform.translation.y))
+            .unwrap_or_else(|_| current_map.goal_position()),
+    };
+    let anchors = Anchors {
+        scene: &scene,
+        map: &current_map,
+        acorn: acorn_position,
+    };
+
+    let cues = timeline.cues();
+    while (game_state.scene_step as usize) < cues.len() {
+        let (at, cue) = cues[game_state.scene_step as usize];
+        if !skipped && at > elapsed {
+            break;
+        }
+        game_state.scene_step += 1;
+        if skipped && !cue.required() {
+            continue;
+        }
+        // the tween catches up with the time since its cue
+        let late = Duration::from_secs_f32((elapsed - at).max(0.));
+        match cue {
+            Cue::Camera(index) => {
+                if let Ok((camera, _)) = camera.single_mut() {
+                    let tween = anchors.tween(&timeline.camera[index]).advanced(late);
+                    commands.entity(camera).insert(tween);
                 }
-                game_state.scene = None;
-                game_state.frozen = false;
-                return;
             }
-            const CAMERA_ON_PLAYER: Duration = Duration::from_millis(500);
-            const CAMERA_TO_GOAL: Duration = Duration::from_millis(1500);
-            let mut camera_on_goal: Duration = Duration::from_millis(2500);
-            let mut camera_back_to_player: Duration = Duration::from_millis(3500);
-
-            if current_map.deref() == &Map::Lava {
-                camera_on_goal = Duration::from_millis(5500);
-                camera_back_to_player = Duration::from_millis(6500);
-            }
-
-            if time
-                .time_since_startup()
-                .lt(&(game_state.scene_start + CAMERA_ON_PLAYER))
-            {
-                return;
+            Cue::Tween(index) => {
+                let motion = &timeline.tweens[index];
+                let target = match motion.target {
+                    Target::Player => player.single().ok(),
+                    Target::Acorn => acorn.single_mut().ok().map(|(acorn, _)| acorn),
+                };
+                if let Some(target) = target {
+                    commands
+                        .entity(target)
+                        .insert(anchors.tween(&motion.motion).advanced(late));
+                }
             }
-
-            if time
-                .time_since_startup()
-                .gt(&(game_state.scene_start + camera_back_to_player))
-            {
-                if acorn_falls {
-                    if let Ok((acorn, _acorn_transform)) = acorn.single_mut() {
-                        commands.entity(acorn).despawn();
+            Cue::Sound(sound) => events.audio_effect.send(AudioEffect {
+                handle: sound_handle(sound, &audio_assets),
+            }),
+            Cue::Swap(SwapTarget::Walls) => {
+                if let CutScene::ActivateButton {
+                    walls: switched, ..
+                } = &scene
+                {
+                    for (wall, open) in switched {
+                        if let Ok((mut wall_state, mut material)) = walls.get_mut(*wall) {
+                            wall_state.open = *open;
+                            apply_wall_state(
+                                &mut commands,
+                                *wall,
+                                &wall_state,
+                                &mut material,
+                                &mut materials,
+                                &textures,
+                            );
+                        }
                     }
                 }
-                if let Ok(mut transform) = camera.single_mut() {
-                    transform.translation.x = camera_from.0;
-                    transform.translation.y = camera_from.1;
-                }
-                game_state.scene = None;
-                game_state.frozen = false;
-                return;
             }
+            Cue::Event(SceneEvent::LevelFinished) => events.level_finished.send(LevelFinished),
+            Cue::Event(SceneEvent::PauseMusic) => events.pause_background.send(PauseBackground),
+        }
+    }
+
+    if !skipped && elapsed < timeline.duration {
+        return;
+    }
 
-            if time
-                .time_since_startup()
-                .gt(&(game_state.scene_start + CAMERA_TO_GOAL))
-                && time
-                    .time_since_startup()
-                    .lt(&(game_state.scene_start + camera_on_goal))
-            {
-                if let Ok((_acorn, mut acorn_transform)) = acorn.single_mut() {
+    if skipped {
+        events.stop_audio_effects.send(StopAudioEffects);
+    }
+    // unfinished tweens would keep moving things after the end state was applied
+    for entity in player
+        .iter()
+        .chain(acorn.iter_mut().map(|(acorn, _)| acorn))
+        .chain(camera.iter_mut().map(|(camera, _)| camera))
+    {
+        commands.entity(entity).remove::<Tween>();
+    }
+    match scene {
+        CutScene::Intro {
+            camera_from,
+            acorn_falls,
+            ..
+        } => {
+            if let Ok((acorn, mut acorn_transform)) = acorn.single_mut() {
+                if game_state.fleeing_acorn {
+                    release_acorn(&mut commands, acorn, &mut acorn_transform, &current_map);
+                } else if acorn_falls {
+                    commands.entity(acorn).despawn();
+                } else {
                     let goal = current_map.goal_position();
-                    let acorn = current_map.acorn_position();
-                    let time_delta = (camera_on_goal - CAMERA_TO_GOAL).as_secs_f32() / 2.;
-                    let mut partial = (2.
-                        - ((game_state.scene_start + camera_on_goal - time.time_since_startup())
-                            .as_secs_f32()
-                            / time_delta))
-                        .clamp(0., 2.);
-                    if !acorn_falls && current_map.deref() != &Map::Lava {
-                        partial /= 2.;
-                    }
-                    if partial < 1. {
-                        if current_map.deref() == &Map::Lava && game_state.scene_step == 0 {
-                            audio_effect.send(AudioEffect {
-                                handle: audio_assets.no_no.clone(),
-                            });
-                            game_state.scene_step += 1;
-                        }
-                        let acorn_path =
-                            (Vec2::new(goal.0, goal.1) - Vec2::new(acorn.0, acorn.1)) * partial;
-                        acorn_transform.translation.x = acorn.0 + acorn_path.x;
-                        acorn_transform.translation.y = acorn.1 + acorn_path.y;
-                    } else if current_map.deref() == &Map::Lava {
-                        let over = if partial < 1.33 {
-                            Vec2::from(goal) + Vec2::new(0., -16.) * ((partial - 1.) * 3.)
-                        } else {
-                            Vec2::from(goal) + Vec2::new(0., -16.) * (1. - ((partial - 1.33) * 1.5))
-                        };
-                        acorn_transform.translation.x = over.x;
-                        acorn_transform.translation.y = over.y;
-                    } else {
-                        acorn_transform.rotation = Quat::from_rotation_z(partial * 2. * PI);
-                        acorn_transform.scale *= 0.95;
-                    }
-                }
-                if let Ok(mut transform) = camera.single_mut() {
-                    transform.translation.x = camera_to.0;
-                    transform.translation.y = camera_to.1;
+                    acorn_transform.translation.x = goal.0;
+                    acorn_transform.translation.y = goal.1;
                 }
-                return;
             }
-
-            if current_map.deref() == &Map::Lava
-                && game_state.scene_step == 1
-                && time
-                    .time_since_startup()
-                    .gt(&(game_state.scene_start + camera_on_goal))
-            {
-                audio_effect.send(AudioEffect {
-                    handle: audio_assets.puh.clone(),
-                });
-                game_state.scene_step += 1;
+            if let Ok((_, mut transform)) = camera.single_mut() {
+                transform.translation.x = camera_from.0;
+                transform.translation.y = camera_from.1;
             }
-
-            let to_animate = if time
-                .time_since_startup()
-                .lt(&(CAMERA_TO_GOAL + game_state.scene_start))
-            {
-                (Vec2::new(camera_to.0 - camera_from.0, camera_to.1 - camera_from.1)
-                    / (CAMERA_TO_GOAL - CAMERA_ON_PLAYER).as_secs_f32())
-                    * time.delta().as_secs_f32()
-            } else {
-                (Vec2::new(camera_from.0 - camera_to.0, camera_from.1 - camera_to.1)
-                    / (camera_back_to_player - camera_on_goal).as_secs_f32())
-                    * time.delta().as_secs_f32()
-            };
-            if let Ok(mut transform) = camera.single_mut() {
-                transform.translation.x += to_animate.x;
-                transform.translation.y += to_animate.y;
+            game_state.frozen = false;
+        }
+        CutScene::ActivateButton { camera_from, .. } => {
+            if let Ok((_, mut transform)) = camera.single_mut() {
+                transform.translation.x = camera_from.0;
+                transform.translation.y = camera_from.1;
             }
+            game_state.frozen = false;
         }
+        CutScene::MapTransition { to, .. } => {
+            queue.clear();
+            *current_map = to;
+            game_state.frozen = false;
+        }
+        CutScene::Won { .. } => {
+            events.background_audio.send(BackgroundAudio {
+                handles: vec![audio_assets.ground_background.clone()],
+            });
+            events.won.send(WonEvent);
+            game_state.won = true;
+        }
+        CutScene::Caught { .. } | CutScene::Died { .. } => (),
     }
+    game_state.scene = None;
 }
 
-fn run_transition_scene(
+struct Anchors<'a> {
+    scene: &'a CutScene,
+    map: &'a Map,
+    acorn: (f32, f32),
+}
+
+impl<'a> Anchors<'a> {
+    fn position(&self, point: &Point) -> Vec2 {
+        let goal = self.map.goal_position();
+        let (from, to) = self.scene.camera_path().unwrap_or((goal, goal));
+        let position = match point.anchor {
+            Anchor::From => from,
+            Anchor::To => to,
+            Anchor::AcornStart => self.map.acorn_position(),
+            Anchor::Acorn => self.acorn,
+            Anchor::Goal => goal,
+        };
+        Vec2::from(position) + Vec2::from(point.offset)
+    }
+
+    fn tween(&self, motion: &Motion) -> Tween {
+        let duration = Duration::from_secs_f32((motion.end - motion.start).max(0.));
+        let mut tween = Tween::new(duration, motion.easing);
+        if let Some((from, to)) = &motion.translation {
+            tween = tween.with(TweenProperty::Translation {
+                from: self.position(from),
+                to: self.position(to),
+            });
+        }
+        if let Some((from, to)) = motion.rotation {
+            tween = tween.with(TweenProperty::Rotation { from, to });
+        }
+        if let Some((from, to)) = motion.scale {
+            tween = tween.with(TweenProperty::Scale { from, to });
+        }
+        tween
+    }
+}
+
+fn sound_handle(sound: Sound, audio_assets: &AudioAssets) -> Handle<AudioSource> {
+    match sound {
+        Sound::Fall => audio_assets.fall.clone(),
+        Sound::Won => audio_assets.won.clone(),
+        Sound::NoNo => audio_assets.no_no.clone(),
+        Sound::Puh => audio_assets.puh.clone(),
+        Sound::WallMoving => audio_assets.wall_moving.clone(),
+    }
+}
+
+fn run_caught_scene(
+    mut commands: Commands,
     mut game_state: ResMut<GameData>,
-    time: Res<Time>,
     actions: Res<Actions>,
-    mut current_map: ResMut<Map>,
-    mut audio_effect: EventWriter<AudioEffect>,
-    mut stop_audio_effects: EventWriter<StopAudioEffects>,
+    current_map: Res<Map>,
     audio_assets: Res<AudioAssets>,
-    mut pause_background: EventWriter<PauseBackground>,
-    mut player: Query<&mut Transform, (With<Player>, Without<PlayerCamera>)>,
-    mut camera: Query<&mut Transform, (With<PlayerCamera>, Without<Player>)>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut player: Query<
+        (Entity, &mut Transform),
+        (With<Player>, Without<PlayerCamera>, Without<Enemy>),
+    >,
+    mut camera: Query<
+        (Entity, &mut Transform),
+        (With<PlayerCamera>, Without<Player>, Without<Enemy>),
+    >,
+    mut enemies: Query<(&mut Enemy, &mut Transform), (Without<Player>, Without<PlayerCamera>)>,
 ) {
     if let Some(scene) = game_state.scene.clone() {
-        if let CutScene::MapTransition {
-            to,
-            camera_to,
+        if let CutScene::Caught {
+            player_from,
             camera_from,
+            camera_to,
         } = scene
         {
-            if actions.scip_scene {
-                stop_audio_effects.send(StopAudioEffects);
-                *current_map = to.clone();
-                game_state.scene = None;
-                game_state.frozen = false;
-                return;
-            }
+            const BACK_TO_START: Duration = Duration::from_millis(1000);
+            let start = current_map.start_position();
+
             if game_state.scene_step == 0 {
                 game_state.scene_step += 1;
-                pause_background.send(PauseBackground);
                 audio_effect.send(AudioEffect {
-                    handle: audio_assets.fall.clone(),
-                })
-            }
-            let camera_scale_offset: Vec3 = Vec3::new(-0.98, -0.98, 0.);
-            let player_scale_offset: Vec3 = Vec3::new(-0.95, -0.95, 0.);
-            const ZOOM: Duration = Duration::from_secs(2);
-
-            if time
-                .time_since_startup()
-                .lt(&(game_state.scene_start + ZOOM))
-            {
-                if let Ok(mut camera_transform) = camera.single_mut() {
-                    camera_transform.scale +=
-                        (camera_scale_offset / ZOOM.as_secs_f32()) * time.delta().as_secs_f32();
-                    let diff = ((Vec2::from(camera_to) - Vec2::from(camera_from))
-                        / ZOOM.as_secs_f32())
-                        * time.delta().as_secs_f32();
-                    if let Ok(mut player_transform) = player.single_mut() {
-                        player_transform.scale +=
-                            (player_scale_offset / ZOOM.as_secs_f32()) * time.delta().as_secs_f32();
-                        player_transform.translation.x += diff.x;
-                        player_transform.translation.y += diff.y;
-                        camera_transform.translation.x = player_transform.translation.x;
-                        camera_transform.translation.y = player_transform.translation.y;
-                    }
+                    handle: audio_assets.no_no.clone(),
+                });
+                if let Ok((player, _)) = player.single_mut() {
+                    commands.entity(player).insert(
+                        Tween::new(BACK_TO_START, Easing::QuadInOut)
+                            .with(TweenProperty::Translation {
+                                from: Vec2::from(player_from),
+                                to: Vec2::from(start),
+                            })
+                            .with(TweenProperty::Rotation { from: 0., to: 1. }),
+                    );
+                }
+                if let Ok((camera, _)) = camera.single_mut() {
+                    commands.entity(camera).insert(
+                        Tween::new(BACK_TO_START, Easing::QuadInOut).with(
+                            TweenProperty::Translation {
+                                from: Vec2::from(camera_from),
+                                to: Vec2::from(camera_to),
+                            },
+                        ),
+                    );
                 }
-                return;
             }
-            *current_map = to.clone();
-            game_state.scene = None;
-            game_state.frozen = false;
-            return;
-        }
-    }
-}
 
-fn run_won_scene(
-    mut game_state: ResMut<GameData>,
-    time: Res<Time>,
-    actions: Res<Actions>,
-    mut audio_effect: EventWriter<AudioEffect>,
-    audio_assets: Res<AudioAssets>,
-    mut stop_audio_effects: EventWriter<StopAudioEffects>,
-    mut pause_background: EventWriter<PauseBackground>,
-    mut background_audio: EventWriter<BackgroundAudio>,
-    mut won: EventWriter<WonEvent>,
-    mut _player: Query<&mut Transform, (With<Player>, Without<PlayerCamera>, Without<Acorn>)>,
-    mut acorn: Query<&mut Transform, (With<Acorn>, Without<PlayerCamera>, Without<Player>)>,
-    mut camera: Query<&mut Transform, (With<PlayerCamera>, Without<Player>, Without<Acorn>)>,
-) {
-    if let Some(scene) = game_state.scene.clone() {
-        if let CutScene::Won = scene {
-            if actions.scip_scene {
-                stop_audio_effects.send(StopAudioEffects);
-                background_audio.send(BackgroundAudio {
-                    handles: vec![audio_assets.ground_background.clone()],
-                });
-                won.send(WonEvent);
-                game_state.won = true;
-                game_state.scene = None;
-                return;
-            }
-            if game_state.scene_step == 0 {
-                if let Ok(mut camera_transform) = camera.single_mut() {
-                    if let Ok(acorn_transform) = acorn.single_mut() {
-                        camera_transform.translation.x = acorn_transform.translation.x;
-                        camera_transform.translation.y = acorn_transform.translation.y;
-                    }
+            if actions.scip_scene || game_state.scene_time > BACK_TO_START {
+                if let Ok((player, mut player_transform)) = player.single_mut() {
+                    commands.entity(player).remove::<Tween>();
+                    player_transform.translation.x = start.0;
+                    player_transform.translation.y = start.1;
+                    player_transform.rotation = Quat::IDENTITY;
                 }
-                game_state.scene_step += 1;
-                pause_background.send(PauseBackground);
-                audio_effect.send(AudioEffect {
-                    handle: audio_assets.won.clone(),
-                })
-            }
-            let camera_scale: Vec3 = Vec3::new(-0.7, -0.7, 0.);
-            const ZOOM: Duration = Duration::from_secs(2);
-
-            if time
-                .time_since_startup()
-                .lt(&(game_state.scene_start + ZOOM))
-            {
-                if let Ok(mut camera_transform) = camera.single_mut() {
-                    camera_transform.scale +=
-                        (camera_scale / ZOOM.as_secs_f32()) * time.delta().as_secs_f32();
+                if let Ok((camera, mut camera_transform)) = camera.single_mut() {
+                    commands.entity(camera).remove::<Tween>();
+                    camera_transform.translation.x = camera_to.0;
+                    camera_transform.translation.y = camera_to.1;
                 }
-                return;
+                for (mut enemy, mut transform) in enemies.iter_mut() {
+                    enemy.reset(&mut transform);
+                }
+                game_state.scene = None;
+                game_state.frozen = false;
             }
-            background_audio.send(BackgroundAudio {
-                handles: vec![audio_assets.ground_background.clone()],
-            });
-            won.send(WonEvent);
-            game_state.won = true;
-            game_state.scene = None;
-            return;
         }
     }
 }
 
-fn run_activate_button_scene(
+fn run_died_scene(
     mut commands: Commands,
-    actions: Res<Actions>,
     mut game_state: ResMut<GameData>,
-    time: Res<Time>,
-    textures: Res<TextureAssets>,
-    mut stop_audio_effects: EventWriter<StopAudioEffects>,
-    mut audio_effect: EventWriter<AudioEffect>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    actions: Res<Actions>,
     audio_assets: Res<AudioAssets>,
-    mut elements: Query<
-        (Entity, &Transform, &mut Handle<ColorMaterial>),
-        (With<ButtonWall>, Without<PlayerCamera>),
-    >,
-    mut camera: Query<&mut Transform, (With<PlayerCamera>, Without<ButtonWall>)>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut current_map: ResMut<Map>,
+    mut roll_back: EventWriter<RollBackSwitches>,
+    mut queue: ResMut<SceneQueue>,
+    mut player: Query<(Entity, &mut Transform), (With<Player>, Without<PlayerCamera>)>,
+    mut camera: Query<(Entity, &mut Transform), (With<PlayerCamera>, Without<Player>)>,
 ) {
     if let Some(scene) = game_state.scene.clone() {
-        if let CutScene::ActivateButton {
-            button,
-            wall,
+        if let CutScene::Died {
+            player_from,
+            respawn,
             camera_from,
             camera_to,
+            restart_level,
+            signals,
         } = scene
         {
-            if actions.scip_scene {
-                stop_audio_effects.send(StopAudioEffects);
-                if let Ok(mut transform) = camera.single_mut() {
-                    transform.translation.x = camera_from.0;
-                    transform.translation.y = camera_from.1;
-                }
-                game_state.scene = None;
-                game_state.frozen = false;
-                if game_state.scene_step == 0 {
-                    if let Ok((_entity, _transform, mut material)) = elements.get_mut(button) {
-                        *material = materials.add(textures.texture_button_down.clone().into());
-                    }
-                    for (entity, transform, mut material) in elements.iter_mut() {
-                        if transform.translation.x == camera_to.0
-                            && transform.translation.y == camera_to.1
-                        {
-                            *material = materials.add(textures.texture_wall_down.clone().into());
-                            commands.entity(entity).remove::<Collide>();
-                        }
-                    }
-                } else if game_state.scene_step < 3 {
-                    if let Ok((entity, _transform, mut material)) = elements.get_mut(wall) {
-                        *material = materials.add(textures.texture_wall_down.clone().into());
-                        commands.entity(entity).remove::<Collide>();
-                    }
-                }
-                return;
-            }
+            const BURNING: Duration = Duration::from_millis(700);
+            const BACK_TO_RESPAWN: Duration = Duration::from_millis(1400);
+
             if game_state.scene_step == 0 {
                 game_state.scene_step += 1;
-                if let Ok((_entity, _transform, mut material)) = elements.get_mut(button) {
-                    *material = materials.add(textures.texture_button_down.clone().into());
-                }
                 audio_effect.send(AudioEffect {
-                    handle: audio_assets.button_click.clone(),
-                })
-            }
-            const CAMERA_ON_PLAYER: Duration = Duration::from_millis(300);
-            const CAMERA_TO_WALL: Duration = Duration::from_millis(1000);
-            const CAMERA_ON_WALL: Duration = Duration::from_millis(1500);
-            const CAMERA_BACK_TO_PLAYER: Duration = Duration::from_millis(2200);
-
-            if time
-                .time_since_startup()
-                .lt(&(game_state.scene_start + CAMERA_ON_PLAYER))
-            {
-                return;
-            }
-
-            if time
-                .time_since_startup()
-                .gt(&(game_state.scene_start + CAMERA_BACK_TO_PLAYER))
-            {
-                if let Ok(mut transform) = camera.single_mut() {
-                    transform.translation.x = camera_from.0;
-                    transform.translation.y = camera_from.1;
+                    handle: audio_assets.puh.clone(),
+                });
+                if let Ok((player, _)) = player.single_mut() {
+                    let burn = Tween::new(BURNING, Easing::QuadIn)
+                        .with(TweenProperty::Translation {
+                            from: Vec2::from(player_from),
+                            to: Vec2::from(player_from),
+                        })
+                        .with(TweenProperty::Rotation { from: 0., to: 2. })
+                        .with(TweenProperty::Scale { from: 1., to: 0. });
+                    // stays invisible at the respawn point while the camera follows
+                    let wait = Tween::new(BACK_TO_RESPAWN - BURNING, Easing::Linear).with(
+                        TweenProperty::Translation {
+                            from: Vec2::from(respawn),
+                            to: Vec2::from(respawn),
+                        },
+                    );
+                    commands.entity(player).insert(burn.then(wait));
+                }
+                if let Ok((camera, _)) = camera.single_mut() {
+                    commands.entity(camera).insert(
+                        Tween::new(BACK_TO_RESPAWN - BURNING, Easing::QuadInOut)
+                            .delayed(BURNING)
+                            .with(TweenProperty::Translation {
+                                from: Vec2::from(camera_from),
+                                to: Vec2::from(camera_to),
+                            }),
+                    );
                 }
-                game_state.scene = None;
-                game_state.frozen = false;
-                return;
             }
 
-            if time
-                .time_since_startup()
-                .gt(&(game_state.scene_start + CAMERA_TO_WALL))
-                && time
-                    .time_since_startup()
-                    .lt(&(game_state.scene_start + CAMERA_ON_WALL))
-            {
-                if game_state.scene_step == 1 {
-                    game_state.scene_step += 1;
-                    audio_effect.send(AudioEffect {
-                        handle: audio_assets.wall_moving.clone(),
-                    })
+            if actions.scip_scene || game_state.scene_time > BACK_TO_RESPAWN {
+                if let Ok((player, mut player_transform)) = player.single_mut() {
+                    commands.entity(player).remove::<Tween>();
+                    player_transform.translation.x = respawn.0;
+                    player_transform.translation.y = respawn.1;
+                    player_transform.rotation = Quat::IDENTITY;
+                    player_transform.scale = Vec3::ONE;
                 }
-                if let Ok(mut transform) = camera.single_mut() {
-                    transform.translation.x = camera_to.0;
-                    transform.translation.y = camera_to.1;
+                if let Ok((camera, mut camera_transform)) = camera.single_mut() {
+                    commands.entity(camera).remove::<Tween>();
+                    camera_transform.translation.x = camera_to.0;
+                    camera_transform.translation.y = camera_to.1;
                 }
-                return;
-            }
-
-            if game_state.scene_step == 2 {
-                game_state.scene_step += 1;
-                if let Ok((entity, _transform, mut material)) = elements.get_mut(wall) {
-                    *material = materials.add(textures.texture_wall_down.clone().into());
-                    commands.entity(entity).remove::<Collide>();
+                if restart_level {
+                    queue.clear();
+                    let map = current_map.clone();
+                    *current_map = map;
+                } else if let Some(signals) = signals {
+                    roll_back.send(RollBackSwitches { signals });
                 }
-            }
-
-            let to_animate = if time
-                .time_since_startup()
-                .lt(&(CAMERA_TO_WALL + game_state.scene_start))
-            {
-                (Vec2::new(camera_to.0 - camera_from.0, camera_to.1 - camera_from.1)
-                    / (CAMERA_TO_WALL - CAMERA_ON_PLAYER).as_secs_f32())
-                    * time.delta().as_secs_f32()
-            } else {
-                (Vec2::new(camera_from.0 - camera_to.0, camera_from.1 - camera_to.1)
-                    / (CAMERA_BACK_TO_PLAYER - CAMERA_ON_WALL).as_secs_f32())
-                    * time.delta().as_secs_f32()
-            };
-            if let Ok(mut transform) = camera.single_mut() {
-                transform.translation.x += to_animate.x;
-                transform.translation.y += to_animate.y;
+                game_state.scene = None;
+                game_state.frozen = false;
             }
         }
     }
 }
 
+// Runs after the scenes, so a waiting scene starts in the frame the previous one finished
 fn trigger_scene(
-    time: Res<Time>,
     mut trigger_scene: EventReader<TriggerScene>,
+    mut queue: ResMut<SceneQueue>,
     mut game_state: ResMut<GameData>,
 ) {
     for event in trigger_scene.iter() {
-        game_state.scene = Some(event.scene.clone());
+        queue.push(event.scene.clone(), game_state.scene.as_ref());
+    }
+    if game_state.scene.is_some() {
+        return;
+    }
+    if let Some(scene) = queue.pop() {
+        game_state.scene = Some(scene);
         game_state.frozen = true;
-        game_state.scene_start = time.time_since_startup();
+        game_state.scene_time = Duration::default();
         game_state.scene_step = 0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn button(camera_to: (f32, f32)) -> CutScene {
+        CutScene::ActivateButton {
+            walls: vec![],
+            camera_from: (0., 0.),
+            camera_to,
+        }
+    }
+
+    #[test]
+    fn pops_by_priority_then_in_order() {
+        let mut queue = SceneQueue::default();
+        queue.push(CutScene::Won { acorn: (0., 0.) }, None);
+        queue.push(button((1., 0.)), None);
+        queue.push(
+            CutScene::Intro {
+                camera_from: (0., 0.),
+                camera_to: (0., 0.),
+                acorn_falls: true,
+            },
+            None,
+        );
+        queue.push(button((2., 0.)), None);
+
+        assert!(matches!(queue.pop(), Some(CutScene::Intro { .. })));
+        assert!(matches!(
+            queue.pop(),
+            Some(CutScene::ActivateButton {
+                camera_to: (x, _),
+                ..
+            }) if x == 1.
+        ));
+        assert!(matches!(
+            queue.pop(),
+            Some(CutScene::ActivateButton {
+                camera_to: (x, _),
+                ..
+            }) if x == 2.
+        ));
+        assert!(matches!(queue.pop(), Some(CutScene::Won { .. })));
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn drops_repeated_scenes() {
+        let caught = CutScene::Caught {
+            player_from: (0., 0.),
+            camera_from: (0., 0.),
+            camera_to: (0., 0.),
+        };
+        let died = CutScene::Died {
+            player_from: (0., 0.),
+            respawn: (0., 0.),
+            camera_from: (0., 0.),
+            camera_to: (0., 0.),
+            restart_level: false,
+            signals: None,
+        };
+        let mut queue = SceneQueue::default();
+        queue.push(died.clone(), Some(&caught));
+        queue.push(button((1., 0.)), None);
+        queue.push(button((1., 0.)), None);
+        queue.push(caught, None);
+        queue.push(died, None);
+
+        assert!(matches!(queue.pop(), Some(CutScene::Caught { .. })));
+        assert!(matches!(queue.pop(), Some(CutScene::ActivateButton { .. })));
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn drops_leaving_the_level_when_sent_back() {
+        let caught = CutScene::Caught {
+            player_from: (0., 0.),
+            camera_from: (0., 0.),
+            camera_to: (0., 0.),
+        };
+        let mut queue = SceneQueue::default();
+        queue.push(
+            CutScene::MapTransition {
+                camera_from: (0., 0.),
+                camera_to: (0., 0.),
+                to: Map::Dirt,
+            },
+            None,
+        );
+        queue.push(button((1., 0.)), None);
+        queue.push(caught, None);
+        queue.push(CutScene::Won { acorn: (0., 0.) }, None);
+
+        assert!(matches!(queue.pop(), Some(CutScene::Caught { .. })));
+        assert!(matches!(queue.pop(), Some(CutScene::ActivateButton { .. })));
+        assert!(queue.pop().is_none());
+    }
+}
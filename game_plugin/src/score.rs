@@ -0,0 +1,277 @@
+use crate::audio::AudioEffect;
+use crate::hints::Hints;
+use crate::loading::{AudioAssets, TextureAssets};
+use crate::map::{LevelEntity, Map, MapSystemLabels, Slot, ACTIVE_ELEMENT_Z, TILE_SIZE};
+use crate::player::{Player, PlayerSystemLabels};
+use crate::storage::GameStorage;
+use crate::{GameData, GameState, TiledMap};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const COLLECTIBLE_SCALE: f32 = 0.4;
+const RECORDS_KEY: &str = "records";
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<LevelScore>()
+            .init_resource::<Playtime>()
+            .init_resource::<BestScores>()
+            .init_resource::<Records>()
+            .add_event::<LevelFinished>()
+            .add_event::<LevelSummary>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .after(MapSystemLabels::DrawMap)
+                    .with_system(draw_collectibles.system())
+                    .with_system(track_time.system())
+                    .with_system(collect.system().after(PlayerSystemLabels::MovePlayer))
+                    .with_system(finish_level.system()),
+            );
+    }
+}
+
+pub struct Collectible {
+    pub slot: Slot,
+    pub collected: bool,
+}
+
+// Sent by the scenes when the squirrel leaves a level
+pub struct LevelFinished;
+
+pub struct LevelSummary {
+    pub level: String,
+    pub result: LevelResult,
+    pub best: LevelResult,
+    pub total: usize,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct LevelResult {
+    pub stars: u8,
+    pub collected: usize,
+    pub time: Duration,
+}
+
+#[derive(Default)]
+pub struct LevelScore {
+    pub collected: usize,
+    pub total: usize,
+    pub time: Duration,
+}
+
+// Time played on the current save, over all levels
+#[derive(Default)]
+pub struct Playtime {
+    pub total: Duration,
+}
+
+#[derive(Default)]
+pub struct BestScores {
+    pub levels: HashMap<String, LevelResult>,
+}
+
+impl LevelResult {
+    // Keeps the best of both results in self; returns whether anything improved
+    fn merge(&mut self, result: &LevelResult) -> bool {
+        let improved = result.stars > self.stars
+            || result.collected > self.collected
+            || result.time < self.time;
+        self.stars = self.stars.max(result.stars);
+        self.collected = self.collected.max(result.collected);
+        self.time = self.time.min(result.time);
+        improved
+    }
+}
+
+impl BestScores {
+    pub fn record(&mut self, level: &str, result: &LevelResult) -> LevelResult {
+        let best = self
+            .levels
+            .entry(level.to_owned())
+            .or_insert_with(|| result.clone());
+        best.merge(result);
+        best.clone()
+    }
+}
+
+// The best results over all saves, kept apart from the slots so deleting a save keeps them
+#[derive(Serialize, Deserialize)]
+pub struct Records {
+    pub levels: HashMap<String, LevelResult>,
+}
+
+impl FromWorld for Records {
+    fn from_world(world: &mut World) -> Self {
+        let storage = world
+            .get_resource::<GameStorage>()
+            .expect("The storage is needed to read the records");
+        let content = match storage.read(RECORDS_KEY) {
+            Some(content) => content,
+            None => {
+                return Records {
+                    levels: HashMap::default(),
+                }
+            }
+        };
+        ron::de::from_str(&content).unwrap_or_else(|error| {
+            warn!("Ignoring the stored records: {}", error);
+            Records {
+                levels: HashMap::default(),
+            }
+        })
+    }
+}
+
+impl Records {
+    fn record(&mut self, level: &str, result: &LevelResult) -> bool {
+        match self.levels.get_mut(level) {
+            Some(record) => record.merge(result),
+            None => {
+                self.levels.insert(level.to_owned(), result.clone());
+                true
+            }
+        }
+    }
+}
+
+// One star for getting down, one for collecting all acorns and one for a quick run without hints
+fn stars(score: &LevelScore, hints_used: usize, par_time: Duration) -> u8 {
+    let mut stars = 1;
+    if score.collected == score.total {
+        stars += 1;
+    }
+    if score.time <= par_time && hints_used == 0 {
+        stars += 1;
+    }
+    stars
+}
+
+fn draw_collectibles(
+    mut commands: Commands,
+    current_map: Res<Map>,
+    maps: Res<Assets<TiledMap>>,
+    textures: Res<TextureAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut score: ResMut<LevelScore>,
+    collectibles: Query<Entity, With<Collectible>>,
+) {
+    if !current_map.is_changed() {
+        return;
+    }
+    for entity in collectibles.iter() {
+        commands.entity(entity).despawn();
+    }
+    let slots = current_map.collectibles(&maps);
+    *score = LevelScore {
+        collected: 0,
+        total: slots.len(),
+        time: Duration::default(),
+    };
+    let material = materials.add(textures.texture_acorn.clone().into());
+    for slot in slots {
+        let slot = current_map.tiled_slot_to_bevy_slot(slot);
+        commands
+            .spawn_bundle(SpriteBundle {
+                material: material.clone(),
+                transform: Transform {
+                    translation: Vec3::new(
+                        slot.column as f32 * TILE_SIZE,
+                        slot.row as f32 * TILE_SIZE,
+                        ACTIVE_ELEMENT_Z,
+                    ),
+                    scale: Vec3::splat(COLLECTIBLE_SCALE),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(LevelEntity)
+            .insert(Collectible {
+                slot,
+                collected: false,
+            });
+    }
+}
+
+fn track_time(
+    time: Res<Time>,
+    game_state: Res<GameData>,
+    mut score: ResMut<LevelScore>,
+    mut playtime: ResMut<Playtime>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    score.time += time.delta();
+    playtime.total += time.delta();
+}
+
+fn collect(
+    game_state: Res<GameData>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut score: ResMut<LevelScore>,
+    mut collectibles: Query<(&mut Collectible, &mut Visible, &Transform)>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    let player_transform = match player_query.single() {
+        Ok(transform) => transform,
+        Err(_) => return,
+    };
+    for (mut collectible, mut visible, transform) in collectibles.iter_mut() {
+        if !collectible.collected
+            && transform
+                .translation
+                .truncate()
+                .distance(player_transform.translation.truncate())
+                < TILE_SIZE / 2.
+        {
+            // only hidden, so rewinding can bring it back
+            collectible.collected = true;
+            visible.is_visible = false;
+            score.collected += 1;
+            audio_effect.send(AudioEffect {
+                handle: audio_assets.button_click.clone(),
+            });
+        }
+    }
+}
+
+fn finish_level(
+    current_map: Res<Map>,
+    score: Res<LevelScore>,
+    hints: Res<Hints>,
+    mut best_scores: ResMut<BestScores>,
+    mut records: ResMut<Records>,
+    mut storage: ResMut<GameStorage>,
+    mut finished: EventReader<LevelFinished>,
+    mut summary: EventWriter<LevelSummary>,
+) {
+    if finished.iter().last().is_none() {
+        return;
+    }
+    let result = LevelResult {
+        stars: stars(&score, hints.used, current_map.par_time()),
+        collected: score.collected,
+        time: score.time,
+    };
+    let best = best_scores.record(current_map.name(), &result);
+    if records.record(current_map.name(), &result) {
+        match ron::ser::to_string_pretty(&*records, Default::default()) {
+            Ok(content) => storage.write(RECORDS_KEY, &content),
+            Err(error) => warn!("Failed to serialize the records: {}", error),
+        }
+    }
+    summary.send(LevelSummary {
+        level: current_map.name().to_owned(),
+        result,
+        best,
+        total: score.total,
+    });
+}
@@ -0,0 +1,485 @@
+use crate::loading::FontAssets;
+use crate::menu::{spawn_panel_button, ButtonMaterials};
+use crate::storage::GameStorage;
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_KEY: &str = "settings";
+const VOLUME_STEP: f32 = 0.1;
+const VOLUME_BAR_WIDTH: f32 = 200.;
+// the step repeat button cycles through these, in milliseconds
+const STEP_REPEATS: [u64; 4] = [120, 180, 240, 300];
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Settings>().add_system_set(
+            SystemSet::new()
+                .with_system(click_settings_button.system())
+                .with_system(update_settings_panel.system())
+                .with_system(rebind_skip_key.system())
+                .with_system(store_settings.system()),
+        );
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Settings {
+    pub music: f32,
+    pub effects: f32,
+    pub muted: bool,
+    pub play_cutscenes: bool,
+    // held to skip a scene
+    #[serde(default = "default_skip_key")]
+    pub skip_key: KeyCode,
+    #[serde(default)]
+    pub skip_seen_scenes: bool,
+    // milliseconds between steps while a key is held in the grid control scheme
+    #[serde(default = "default_step_repeat")]
+    step_repeat: u64,
+}
+
+fn default_skip_key() -> KeyCode {
+    KeyCode::Space
+}
+
+fn default_step_repeat() -> u64 {
+    180
+}
+
+impl Settings {
+    pub fn music_volume(&self) -> f32 {
+        if self.muted {
+            0.
+        } else {
+            self.music
+        }
+    }
+
+    pub fn effects_volume(&self) -> f32 {
+        if self.muted {
+            0.
+        } else {
+            self.effects
+        }
+    }
+
+    pub fn step_repeat(&self) -> Duration {
+        Duration::from_millis(self.step_repeat)
+    }
+
+    fn next_step_repeat(&self) -> u64 {
+        STEP_REPEATS
+            .iter()
+            .copied()
+            .find(|step_repeat| *step_repeat > self.step_repeat)
+            .unwrap_or(STEP_REPEATS[0])
+    }
+
+    fn volume_mut(&mut self, channel: VolumeChannel) -> &mut f32 {
+        match channel {
+            VolumeChannel::Music => &mut self.music,
+            VolumeChannel::Effects => &mut self.effects,
+        }
+    }
+}
+
+impl FromWorld for Settings {
+    fn from_world(world: &mut World) -> Self {
+        let defaults = Settings {
+            music: 0.4,
+            effects: 0.4,
+            muted: false,
+            play_cutscenes: true,
+            skip_key: default_skip_key(),
+            skip_seen_scenes: false,
+            step_repeat: default_step_repeat(),
+        };
+        let storage = world
+            .get_resource::<GameStorage>()
+            .expect("The storage is needed to read the settings");
+        let content = match storage.read(SETTINGS_KEY) {
+            Some(content) => content,
+            None => return defaults,
+        };
+        ron::de::from_str(&content).unwrap_or_else(|error| {
+            warn!("Ignoring the stored settings: {}", error);
+            defaults
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum VolumeChannel {
+    Music,
+    Effects,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SettingsButton {
+    Quieter(VolumeChannel),
+    Louder(VolumeChannel),
+    Mute,
+    Cutscenes,
+    SkipKey,
+    SkipSeenScenes,
+    StepRepeat,
+    Close,
+}
+
+pub struct SettingsPanel;
+
+// The skip key button waits for the next key press
+struct AwaitingKey;
+
+struct VolumeBar {
+    channel: VolumeChannel,
+}
+
+// Opened from the main menu and the pause menu; returns the panel so the caller can tag it
+pub fn spawn_settings_panel(
+    commands: &mut Commands,
+    font_assets: &FontAssets,
+    button_materials: &ButtonMaterials,
+    materials: &mut Assets<ColorMaterial>,
+    settings: &Settings,
+) -> Entity {
+    let font = font_assets.fira_sans.clone();
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(20.),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(SettingsPanel)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        align_items: AlignItems::Center,
+                        padding: Rect::all(Val::Px(10.)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::rgba(0.1, 0.1, 0.1, 0.9).into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for (channel, label, volume) in [
+                        (VolumeChannel::Music, "Music", settings.music),
+                        (VolumeChannel::Effects, "Effects", settings.effects),
+                    ]
+                    .iter()
+                    {
+                        spawn_volume_row(
+                            parent,
+                            button_materials,
+                            materials,
+                            &font,
+                            *channel,
+                            label,
+                            *volume,
+                        );
+                    }
+                    spawn_panel_button(
+                        parent,
+                        button_materials,
+                        &font,
+                        SettingsButton::Mute,
+                        300.,
+                        mute_text(settings.muted),
+                    );
+                    spawn_panel_button(
+                        parent,
+                        button_materials,
+                        &font,
+                        SettingsButton::Cutscenes,
+                        300.,
+                        cutscenes_text(settings.play_cutscenes),
+                    );
+                    spawn_panel_button(
+                        parent,
+                        button_materials,
+                        &font,
+                        SettingsButton::SkipKey,
+                        300.,
+                        &skip_key_text(settings.skip_key),
+                    );
+                    spawn_panel_button(
+                        parent,
+                        button_materials,
+                        &font,
+                        SettingsButton::SkipSeenScenes,
+                        300.,
+                        skip_seen_scenes_text(settings.skip_seen_scenes),
+                    );
+                    spawn_panel_button(
+                        parent,
+                        button_materials,
+                        &font,
+                        SettingsButton::StepRepeat,
+                        300.,
+                        &step_repeat_text(settings.step_repeat),
+                    );
+                    spawn_panel_button(
+                        parent,
+                        button_materials,
+                        &font,
+                        SettingsButton::Close,
+                        120.,
+                        "Back",
+                    );
+                });
+        })
+        .id()
+}
+
+fn spawn_volume_row(
+    parent: &mut ChildBuilder,
+    button_materials: &ButtonMaterials,
+    materials: &mut Assets<ColorMaterial>,
+    font: &Handle<Font>,
+    channel: VolumeChannel,
+    label: &str,
+    volume: f32,
+) {
+    parent
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    size: Size::new(Val::Px(100.), Val::Auto),
+                    margin: Rect::all(Val::Px(4.)),
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    label,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 22.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+            spawn_panel_button(
+                parent,
+                button_materials,
+                font,
+                SettingsButton::Quieter(channel),
+                40.,
+                "-",
+            );
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(VOLUME_BAR_WIDTH), Val::Px(16.)),
+                        margin: Rect::all(Val::Px(4.)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::rgb(0.25, 0.25, 0.25).into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(volume * 100.), Val::Percent(100.)),
+                                ..Default::default()
+                            },
+                            material: materials.add(Color::rgb(0.9, 0.8, 0.3).into()),
+                            ..Default::default()
+                        })
+                        .insert(VolumeBar { channel });
+                });
+            spawn_panel_button(
+                parent,
+                button_materials,
+                font,
+                SettingsButton::Louder(channel),
+                40.,
+                "+",
+            );
+        });
+}
+
+fn mute_text(muted: bool) -> &'static str {
+    if muted {
+        "Sound: muted"
+    } else {
+        "Sound: on"
+    }
+}
+
+fn cutscenes_text(play_cutscenes: bool) -> &'static str {
+    if play_cutscenes {
+        "Cutscenes: play"
+    } else {
+        "Cutscenes: skip"
+    }
+}
+
+fn skip_key_text(key: KeyCode) -> String {
+    format!("Skip key: {:?}", key)
+}
+
+fn skip_seen_scenes_text(skip_seen_scenes: bool) -> &'static str {
+    if skip_seen_scenes {
+        "Seen scenes: skip"
+    } else {
+        "Seen scenes: play"
+    }
+}
+
+fn step_repeat_text(step_repeat: u64) -> String {
+    format!("Grid step repeat: {} ms", step_repeat)
+}
+
+fn click_settings_button(
+    mut commands: Commands,
+    button_materials: Res<ButtonMaterials>,
+    mut settings: ResMut<Settings>,
+    mut interaction_query: Query<
+        (&SettingsButton, &Interaction, &mut Handle<ColorMaterial>),
+        Changed<Interaction>,
+    >,
+    panels: Query<Entity, With<SettingsPanel>>,
+) {
+    for (button, interaction, mut material) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => match *button {
+                SettingsButton::Quieter(channel) => {
+                    let volume = settings.volume_mut(channel);
+                    *volume = (*volume - VOLUME_STEP).max(0.);
+                }
+                SettingsButton::Louder(channel) => {
+                    let volume = settings.volume_mut(channel);
+                    *volume = (*volume + VOLUME_STEP).min(1.);
+                }
+                SettingsButton::Mute => settings.muted = !settings.muted,
+                SettingsButton::Cutscenes => settings.play_cutscenes = !settings.play_cutscenes,
+                SettingsButton::SkipSeenScenes => {
+                    settings.skip_seen_scenes = !settings.skip_seen_scenes
+                }
+                SettingsButton::StepRepeat => settings.step_repeat = settings.next_step_repeat(),
+                // handled while rebinding
+                SettingsButton::SkipKey => (),
+                SettingsButton::Close => {
+                    for panel in panels.iter() {
+                        commands.entity(panel).despawn_recursive();
+                    }
+                }
+            },
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
+
+fn update_settings_panel(
+    settings: Res<Settings>,
+    mut bars: Query<(&VolumeBar, &mut Style)>,
+    buttons: Query<(&SettingsButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (bar, mut style) in bars.iter_mut() {
+        let volume = match bar.channel {
+            VolumeChannel::Music => settings.music,
+            VolumeChannel::Effects => settings.effects,
+        };
+        style.size.width = Val::Percent(volume * 100.);
+    }
+    for (button, children) in buttons.iter() {
+        let text = match button {
+            SettingsButton::Mute => mute_text(settings.muted).to_string(),
+            SettingsButton::Cutscenes => cutscenes_text(settings.play_cutscenes).to_string(),
+            SettingsButton::SkipKey => skip_key_text(settings.skip_key),
+            SettingsButton::SkipSeenScenes => {
+                skip_seen_scenes_text(settings.skip_seen_scenes).to_string()
+            }
+            SettingsButton::StepRepeat => step_repeat_text(settings.step_repeat),
+            _ => continue,
+        };
+        if let Ok(mut button_text) = text_query.get_mut(children[0]) {
+            button_text.sections[0].value = text;
+        }
+    }
+}
+
+// Escape keeps the old key
+fn rebind_skip_key(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    buttons: Query<(
+        Entity,
+        &SettingsButton,
+        &Interaction,
+        &Children,
+        Option<&AwaitingKey>,
+    )>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (entity, button, interaction, children, awaiting) in buttons.iter() {
+        if *button != SettingsButton::SkipKey {
+            continue;
+        }
+        let text = if awaiting.is_none() {
+            if *interaction != Interaction::Clicked {
+                continue;
+            }
+            commands.entity(entity).insert(AwaitingKey);
+            "Press a key".to_string()
+        } else {
+            let key = match keyboard_input.get_just_pressed().next() {
+                Some(key) => *key,
+                None => continue,
+            };
+            if key != KeyCode::Escape {
+                settings.skip_key = key;
+            }
+            commands.entity(entity).remove::<AwaitingKey>();
+            skip_key_text(settings.skip_key)
+        };
+        if let Ok(mut button_text) = text_query.get_mut(children[0]) {
+            button_text.sections[0].value = text;
+        }
+    }
+}
+
+fn store_settings(settings: Res<Settings>, mut storage: ResMut<GameStorage>) {
+    if !settings.is_changed() || settings.is_added() {
+        return;
+    }
+    match ron::ser::to_string_pretty(&*settings, Default::default()) {
+        Ok(content) => storage.write(SETTINGS_KEY, &content),
+        Err(error) => warn!("Failed to serialize the settings: {}", error),
+    }
+}
@@ -0,0 +1,240 @@
+use crate::actions::Actions;
+use crate::loading::FontAssets;
+use crate::map::{LevelEntity, Map};
+use crate::settings::Settings;
+use crate::{GameData, GameState};
+use bevy::prelude::*;
+use bevy::utils::{Duration, HashSet};
+use std::f32::consts::PI;
+
+const SKIP_HOLD: Duration = Duration::from_millis(800);
+const SKIP_SEGMENTS: usize = 12;
+const RING_SIZE: f32 = 36.;
+const SEGMENT_SIZE: f32 = 6.;
+
+pub struct SkipPlugin;
+
+impl Plugin for SkipPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SkipHold>()
+            .init_resource::<SeenScenes>()
+            .init_resource::<SkipMaterials>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(hold_to_skip.system().label(SkipSystemLabels::Hold))
+                    .with_system(show_skip_prompt.system())
+                    .with_system(update_skip_progress.system()),
+            );
+    }
+}
+
+#[derive(SystemLabel, Clone, Hash, Debug, Eq, PartialEq)]
+pub enum SkipSystemLabels {
+    Hold,
+}
+
+// Scenes that were played or skipped on the running save
+#[derive(Default)]
+pub struct SeenScenes {
+    pub scenes: HashSet<String>,
+}
+
+#[derive(Default)]
+struct SkipHold {
+    held: Duration,
+    // after a skip, the input has to be let go before the next scene can be skipped
+    blocked: bool,
+    scene: Option<String>,
+    // whether the active scene had been seen before it started
+    seen_before: bool,
+}
+
+struct SkipMaterials {
+    empty: Handle<ColorMaterial>,
+    filled: Handle<ColorMaterial>,
+}
+
+impl FromWorld for SkipMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        SkipMaterials {
+            empty: materials.add(Color::rgba(0.9, 0.9, 0.9, 0.3).into()),
+            filled: materials.add(Color::rgb(0.9, 0.8, 0.3).into()),
+        }
+    }
+}
+
+struct SkipPrompt;
+
+struct SkipSegment {
+    index: usize,
+}
+
+fn holding_skip(
+    settings: &Settings,
+    keyboard_input: &Input<KeyCode>,
+    gamepad_buttons: &Input<GamepadButton>,
+    touches: &Touches,
+) -> bool {
+    keyboard_input.pressed(settings.skip_key)
+        || gamepad_buttons.get_pressed().any(|button| {
+            matches!(
+                button.1,
+                GamepadButtonType::South | GamepadButtonType::Start
+            )
+        })
+        || touches.iter().next().is_some()
+}
+
+fn hold_to_skip(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    game_state: Res<GameData>,
+    current_map: Res<Map>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    touches: Res<Touches>,
+    mut hold: ResMut<SkipHold>,
+    mut seen: ResMut<SeenScenes>,
+    mut actions: ResMut<Actions>,
+) {
+    actions.scip_scene = false;
+    let scene = game_state
+        .scene
+        .as_ref()
+        .and_then(|scene| scene.seen_key(&current_map));
+    if hold.scene != scene {
+        hold.seen_before = match &scene {
+            Some(scene) => !seen.scenes.insert(scene.clone()),
+            None => false,
+        };
+        hold.scene = scene;
+        hold.held = Duration::default();
+    }
+    if game_state.scene.is_none() {
+        return;
+    }
+    // with cutscenes turned off, every scene is skipped as soon as it starts
+    if !settings.play_cutscenes || (settings.skip_seen_scenes && hold.seen_before) {
+        actions.scip_scene = true;
+        return;
+    }
+    if !holding_skip(&settings, &keyboard_input, &gamepad_buttons, &touches) {
+        hold.held = Duration::default();
+        hold.blocked = false;
+        return;
+    }
+    if hold.blocked {
+        return;
+    }
+    hold.held += time.delta();
+    if hold.held >= SKIP_HOLD {
+        actions.scip_scene = true;
+        hold.held = Duration::default();
+        hold.blocked = true;
+    }
+}
+
+fn show_skip_prompt(
+    mut commands: Commands,
+    game_state: Res<GameData>,
+    settings: Res<Settings>,
+    font_assets: Res<FontAssets>,
+    skip_materials: Res<SkipMaterials>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    prompts: Query<Entity, With<SkipPrompt>>,
+) {
+    let showing = prompts.iter().next().is_some();
+    let wanted = game_state.scene.is_some() && settings.play_cutscenes;
+    if showing && !wanted {
+        for prompt in prompts.iter() {
+            commands.entity(prompt).despawn_recursive();
+        }
+    }
+    if showing || !wanted {
+        return;
+    }
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(20.),
+                    bottom: Val::Px(20.),
+                    ..Default::default()
+                },
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(SkipPrompt)
+        .insert(LevelEntity)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(RING_SIZE), Val::Px(RING_SIZE)),
+                        margin: Rect::all(Val::Px(6.)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::NONE.into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    // clockwise from the top, like a clock hand
+                    let radius = (RING_SIZE - SEGMENT_SIZE) / 2.;
+                    for index in 0..SKIP_SEGMENTS {
+                        let angle = PI / 2. - index as f32 * 2. * PI / SKIP_SEGMENTS as f32;
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Px(SEGMENT_SIZE), Val::Px(SEGMENT_SIZE)),
+                                    position_type: PositionType::Absolute,
+                                    position: Rect {
+                                        left: Val::Px(radius + radius * angle.cos()),
+                                        bottom: Val::Px(radius + radius * angle.sin()),
+                                        ..Default::default()
+                                    },
+                                    ..Default::default()
+                                },
+                                material: skip_materials.empty.clone(),
+                                ..Default::default()
+                            })
+                            .insert(SkipSegment { index });
+                    }
+                });
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    format!("Hold {:?} to skip", settings.skip_key),
+                    TextStyle {
+                        font: font_assets.fira_sans.clone(),
+                        font_size: 20.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+fn update_skip_progress(
+    hold: Res<SkipHold>,
+    skip_materials: Res<SkipMaterials>,
+    mut segments: Query<(&SkipSegment, &mut Handle<ColorMaterial>)>,
+) {
+    let filled =
+        (hold.held.as_secs_f32() / SKIP_HOLD.as_secs_f32() * SKIP_SEGMENTS as f32).ceil() as usize;
+    for (segment, mut material) in segments.iter_mut() {
+        let wanted = if segment.index < filled {
+            &skip_materials.filled
+        } else {
+            &skip_materials.empty
+        };
+        if *material != *wanted {
+            *material = wanted.clone();
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+#[cfg(not(feature = "web"))]
+use std::fs;
+#[cfg(not(feature = "web"))]
+use std::path::PathBuf;
+
+// Where saves, settings and records are kept between runs
+pub trait Storage: Send + Sync {
+    fn read(&self, key: &str) -> Option<String>;
+    fn write(&mut self, key: &str, value: &str) -> Result<(), String>;
+    fn remove(&mut self, key: &str) -> Result<(), String>;
+}
+
+pub struct GameStorage {
+    backend: Box<dyn Storage>,
+}
+
+impl GameStorage {
+    pub fn new(backend: Box<dyn Storage>) -> Self {
+        GameStorage { backend }
+    }
+
+    pub fn read(&self, key: &str) -> Option<String> {
+        self.backend.read(key)
+    }
+
+    pub fn write(&mut self, key: &str, value: &str) {
+        if let Err(error) = self.backend.write(key, value) {
+            warn!("Failed to store '{}': {}", key, error);
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        if let Err(error) = self.backend.remove(key) {
+            warn!("Failed to remove '{}': {}", key, error);
+        }
+    }
+}
+
+impl Default for GameStorage {
+    #[cfg(feature = "web")]
+    fn default() -> Self {
+        GameStorage::new(Box::new(WebStorage))
+    }
+
+    #[cfg(not(feature = "web"))]
+    fn default() -> Self {
+        match dirs::data_dir() {
+            Some(directory) => GameStorage::new(Box::new(FileStorage {
+                directory: directory.join("the_chase"),
+            })),
+            None => GameStorage::new(Box::new(MemoryStorage::default())),
+        }
+    }
+}
+
+// One ron file per key in the platform's data directory
+#[cfg(not(feature = "web"))]
+pub struct FileStorage {
+    pub directory: PathBuf,
+}
+
+#[cfg(not(feature = "web"))]
+impl FileStorage {
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{}.ron", key))
+    }
+}
+
+#[cfg(not(feature = "web"))]
+impl Storage for FileStorage {
+    fn read(&self, key: &str) -> Option<String> {
+        fs::read_to_string(self.path(key)).ok()
+    }
+
+    fn write(&mut self, key: &str, value: &str) -> Result<(), String> {
+        fs::create_dir_all(&self.directory).map_err(|error| error.to_string())?;
+        fs::write(self.path(key), value).map_err(|error| error.to_string())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), String> {
+        let path = self.path(key);
+        if !path.exists() {
+            return Ok(());
+        }
+        fs::remove_file(path).map_err(|error| error.to_string())
+    }
+}
+
+// Forgets everything on exit, for tests and platforms without a data directory
+#[derive(Default)]
+pub struct MemoryStorage {
+    values: HashMap<String, String>,
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, key: &str) -> Option<String> {
+        self.values.get(key).cloned()
+    }
+
+    fn write(&mut self, key: &str, value: &str) -> Result<(), String> {
+        self.values.insert(key.to_owned(), value.to_owned());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), String> {
+        self.values.remove(key);
+        Ok(())
+    }
+}
+
+// The browser's local storage; looked up on every call since it can't be shared between threads
+#[cfg(feature = "web")]
+pub struct WebStorage;
+
+#[cfg(feature = "web")]
+impl WebStorage {
+    fn local_storage() -> Result<web_sys::Storage, String> {
+        web_sys::window()
+            .ok_or_else(|| "no window".to_owned())?
+            .local_storage()
+            .map_err(|error| format!("{:?}", error))?
+            .ok_or_else(|| "no local storage".to_owned())
+    }
+
+    fn key(key: &str) -> String {
+        format!("the_chase.{}", key)
+    }
+}
+
+#[cfg(feature = "web")]
+impl Storage for WebStorage {
+    fn read(&self, key: &str) -> Option<String> {
+        WebStorage::local_storage()
+            .ok()?
+            .get_item(&WebStorage::key(key))
+            .ok()
+            .flatten()
+    }
+
+    fn write(&mut self, key: &str, value: &str) -> Result<(), String> {
+        WebStorage::local_storage()?
+            .set_item(&WebStorage::key(key), value)
+            .map_err(|error| format!("{:?}", error))
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), String> {
+        WebStorage::local_storage()?
+            .remove_item(&WebStorage::key(key))
+            .map_err(|error| format!("{:?}", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_storage_round_trip() {
+        let mut storage = GameStorage::new(Box::new(MemoryStorage::default()));
+        assert_eq!(storage.read("slot_1"), None);
+
+        storage.write("slot_1", "first");
+        assert_eq!(storage.read("slot_1"), Some("first".to_owned()));
+        storage.write("slot_1", "second");
+        assert_eq!(storage.read("slot_1"), Some("second".to_owned()));
+        assert_eq!(storage.read("slot_2"), None);
+
+        storage.remove("slot_1");
+        assert_eq!(storage.read("slot_1"), None);
+        // removing a missing key is fine
+        storage.remove("slot_1");
+    }
+}
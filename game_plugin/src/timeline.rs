@@ -0,0 +1,192 @@
+use crate::tween::Easing;
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+pub struct TimelinePlugin;
+
+impl Plugin for TimelinePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<Timeline>()
+            .init_asset_loader::<TimelineLoader>();
+    }
+}
+
+// A cutscene described in a `.timeline` file under assets/cutscenes. All times are in seconds
+// since the scene started.
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "3f1c2a6e-8d4b-4f0a-9b7e-5c2d1e6f8a90"]
+pub struct Timeline {
+    pub duration: f32,
+    #[serde(default)]
+    pub camera: Vec<Motion>,
+    #[serde(default)]
+    pub tweens: Vec<EntityMotion>,
+    #[serde(default)]
+    pub audio: Vec<AudioCue>,
+    #[serde(default)]
+    pub materials: Vec<MaterialSwap>,
+    #[serde(default)]
+    pub events: Vec<EventCue>,
+}
+
+// Positions are given relative to the scene, so the same file works for every button or exit
+#[derive(Deserialize, Clone, Copy)]
+pub enum Anchor {
+    From,
+    To,
+    AcornStart,
+    Acorn,
+    Goal,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct Point {
+    pub anchor: Anchor,
+    #[serde(default)]
+    pub offset: (f32, f32),
+}
+
+#[derive(Deserialize)]
+pub struct Motion {
+    pub start: f32,
+    pub end: f32,
+    #[serde(default)]
+    pub translation: Option<(Point, Point)>,
+    // in full turns
+    #[serde(default)]
+    pub rotation: Option<(f32, f32)>,
+    #[serde(default)]
+    pub scale: Option<(f32, f32)>,
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+pub enum Target {
+    Player,
+    Acorn,
+}
+
+#[derive(Deserialize)]
+pub struct EntityMotion {
+    pub target: Target,
+    pub motion: Motion,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum Sound {
+    Fall,
+    Won,
+    NoNo,
+    Puh,
+    WallMoving,
+}
+
+#[derive(Deserialize)]
+pub struct AudioCue {
+    pub at: f32,
+    pub sound: Sound,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum SwapTarget {
+    // the walls switched by the button of this scene
+    Walls,
+}
+
+#[derive(Deserialize)]
+pub struct MaterialSwap {
+    pub at: f32,
+    pub target: SwapTarget,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum SceneEvent {
+    LevelFinished,
+    PauseMusic,
+}
+
+#[derive(Deserialize)]
+pub struct EventCue {
+    pub at: f32,
+    pub event: SceneEvent,
+}
+
+#[derive(Clone, Copy)]
+pub enum Cue {
+    // index into the camera motions
+    Camera(usize),
+    // index into the entity motions
+    Tween(usize),
+    Sound(Sound),
+    Swap(SwapTarget),
+    Event(SceneEvent),
+}
+
+impl Cue {
+    // Cues that change the game and have to happen even if the scene is skipped
+    pub fn required(&self) -> bool {
+        match self {
+            Cue::Camera(_) | Cue::Tween(_) | Cue::Sound(_) => false,
+            Cue::Swap(_) => true,
+            Cue::Event(SceneEvent::LevelFinished) => true,
+            Cue::Event(SceneEvent::PauseMusic) => false,
+        }
+    }
+}
+
+impl Timeline {
+    // All cues of the timeline, in the order they fire. Motions start a tween on their target,
+    // so motions of the same target should not overlap.
+    pub fn cues(&self) -> Vec<(f32, Cue)> {
+        let mut cues: Vec<(f32, Cue)> = self
+            .camera
+            .iter()
+            .enumerate()
+            .map(|(index, motion)| (motion.start, Cue::Camera(index)))
+            .chain(
+                self.tweens
+                    .iter()
+                    .enumerate()
+                    .map(|(index, tween)| (tween.motion.start, Cue::Tween(index))),
+            )
+            .chain(self.audio.iter().map(|cue| (cue.at, Cue::Sound(cue.sound))))
+            .chain(
+                self.materials
+                    .iter()
+                    .map(|swap| (swap.at, Cue::Swap(swap.target))),
+            )
+            .chain(
+                self.events
+                    .iter()
+                    .map(|cue| (cue.at, Cue::Event(cue.event))),
+            )
+            .collect();
+        cues.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        cues
+    }
+}
+
+#[derive(Default)]
+pub struct TimelineLoader;
+
+impl AssetLoader for TimelineLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let timeline: Timeline = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(timeline));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["timeline"]
+    }
+}
@@ -0,0 +1,211 @@
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use serde::Deserialize;
+use std::f32::consts::PI;
+
+// a stalled frame should not jump to the end of an animation
+const MAX_TWEEN_FRAME: Duration = Duration::from_millis(100);
+
+pub struct TweenPlugin;
+
+impl Plugin for TweenPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<TweenCompleted>().add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(animate_tweens.system().label(TweenSystemLabels::Animate)),
+        );
+    }
+}
+
+// Scenes run after the tweens, so their end state is not overwritten in the same frame
+#[derive(SystemLabel, Clone, Hash, Debug, Eq, PartialEq)]
+pub enum TweenSystemLabels {
+    Animate,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicInOut,
+    SineInOut,
+    // overshoots a little before settling
+    BackOut,
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::Linear
+    }
+}
+
+impl Easing {
+    pub fn ease(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => t * (2. - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2. * t * t
+                } else {
+                    1. - (-2. * t + 2.).powi(2) / 2.
+                }
+            }
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4. * t * t * t
+                } else {
+                    1. - (-2. * t + 2.).powi(3) / 2.
+                }
+            }
+            Easing::SineInOut => -((PI * t).cos() - 1.) / 2.,
+            Easing::BackOut => {
+                const OVERSHOOT: f32 = 1.70158;
+                let t = t - 1.;
+                1. + (OVERSHOOT + 1.) * t.powi(3) + OVERSHOOT * t.powi(2)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum TweenProperty {
+    // the z coordinate is left alone
+    Translation { from: Vec2, to: Vec2 },
+    // in full turns around z
+    Rotation { from: f32, to: f32 },
+    Scale { from: f32, to: f32 },
+    // needs a material of its own, shared materials would all change
+    Color { from: Color, to: Color },
+}
+
+// Animates an entity and removes itself when done. Chained tweens start when the previous one finished.
+pub struct Tween {
+    properties: Vec<TweenProperty>,
+    duration: Duration,
+    delay: Duration,
+    easing: Easing,
+    tag: u32,
+    elapsed: Duration,
+    next: Option<Box<Tween>>,
+}
+
+// Sent for every finished tween of a chain
+pub struct TweenCompleted {
+    pub entity: Entity,
+    pub tag: u32,
+}
+
+impl Tween {
+    pub fn new(duration: Duration, easing: Easing) -> Self {
+        Tween {
+            properties: vec![],
+            duration,
+            delay: Duration::default(),
+            easing,
+            tag: 0,
+            elapsed: Duration::default(),
+            next: None,
+        }
+    }
+
+    pub fn with(mut self, property: TweenProperty) -> Self {
+        self.properties.push(property);
+        self
+    }
+
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn tagged(mut self, tag: u32) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    // Start as if the tween had already been running for a while
+    pub fn advanced(mut self, by: Duration) -> Self {
+        self.elapsed = by;
+        self
+    }
+
+    pub fn then(mut self, next: Tween) -> Self {
+        match self.next {
+            Some(chained) => self.next = Some(Box::new(chained.then(next))),
+            None => self.next = Some(Box::new(next)),
+        }
+        self
+    }
+
+    fn progress(&self) -> f32 {
+        if self.duration == Duration::default() {
+            return 1.;
+        }
+        let t = self.elapsed.saturating_sub(self.delay).as_secs_f32() / self.duration.as_secs_f32();
+        self.easing.ease(t.clamp(0., 1.))
+    }
+}
+
+fn animate_tweens(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut completed: EventWriter<TweenCompleted>,
+    mut tweens: Query<(
+        Entity,
+        &mut Tween,
+        &mut Transform,
+        Option<&Handle<ColorMaterial>>,
+    )>,
+) {
+    for (entity, mut tween, mut transform, material) in tweens.iter_mut() {
+        tween.elapsed += time.delta().min(MAX_TWEEN_FRAME);
+        if tween.elapsed < tween.delay {
+            continue;
+        }
+        let progress = tween.progress();
+        for property in tween.properties.iter() {
+            match *property {
+                TweenProperty::Translation { from, to } => {
+                    let position = from.lerp(to, progress);
+                    transform.translation.x = position.x;
+                    transform.translation.y = position.y;
+                }
+                TweenProperty::Rotation { from, to } => {
+                    transform.rotation =
+                        Quat::from_rotation_z((from + (to - from) * progress) * 2. * PI);
+                }
+                TweenProperty::Scale { from, to } => {
+                    let scale = from + (to - from) * progress;
+                    transform.scale = Vec3::new(scale, scale, transform.scale.z);
+                }
+                TweenProperty::Color { from, to } => {
+                    if let Some(material) = material.and_then(|handle| materials.get_mut(handle)) {
+                        let from = Vec4::from(from);
+                        material.color = from.lerp(Vec4::from(to), progress).into();
+                    }
+                }
+            }
+        }
+        let end = tween.delay + tween.duration;
+        if tween.elapsed < end {
+            continue;
+        }
+        completed.send(TweenCompleted {
+            entity,
+            tag: tween.tag,
+        });
+        let overshoot = tween.elapsed - end;
+        match tween.next.take() {
+            Some(next) => *tween = next.advanced(overshoot),
+            None => {
+                commands.entity(entity).remove::<Tween>();
+            }
+        }
+    }
+}
@@ -1,18 +1,51 @@
+use crate::dash::Stamina;
+use crate::digging::Digs;
+use crate::inventory::Inventory;
 use crate::loading::FontAssets;
-use crate::map::{Acorn, Map};
+use crate::map::{Acorn, LevelEntity, Map};
 use crate::menu::ButtonMaterials;
-use crate::{GameData, GameState};
+use crate::player::Player;
+use crate::rewind::Rewind;
+use crate::routes::{all_routes, route_name, ExploredRoutes, Route};
+use crate::score::{LevelScore, LevelSummary};
+use crate::{GameData, GameState, TiledMap};
 use bevy::prelude::*;
 
 pub struct UiPlugin;
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_event::<WonEvent>().add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .with_system(spawn_retry_ui.system())
-                .with_system(click_retry_button.system()),
-        );
+        app.add_event::<WonEvent>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(spawn_inventory_hud.system())
+                    .with_system(spawn_digs_hud.system())
+                    .with_system(spawn_stamina_hud.system())
+                    .with_system(spawn_collectibles_hud.system())
+                    .with_system(spawn_rewind_hud.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(spawn_retry_ui.system())
+                    .with_system(click_retry_button.system())
+                    .with_system(click_routes_button.system())
+                    .with_system(update_inventory_hud.system())
+                    .with_system(update_digs_hud.system())
+                    .with_system(update_stamina_hud.system())
+                    .with_system(update_collectibles_hud.system())
+                    .with_system(update_rewind_hud.system())
+                    .with_system(spawn_level_summary.system())
+                    .with_system(remove_level_summary.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing)
+                    .with_system(remove_inventory_hud.system())
+                    .with_system(remove_digs_hud.system())
+                    .with_system(remove_stamina_hud.system())
+                    .with_system(remove_collectibles_hud.system())
+                    .with_system(remove_rewind_hud.system())
+                    .with_system(remove_all_level_summaries.system()),
+            );
     }
 }
 
@@ -20,14 +53,98 @@ pub struct WonEvent;
 
 struct Ui;
 struct RetryButton;
+struct RoutesButton;
+struct DepthTree;
+struct InventoryHud;
+struct DigsHud;
+struct StaminaHud;
+struct StaminaBar;
+struct CollectiblesHud;
+struct RewindHud;
+struct LevelSummaryPanel;
+
+const HUD_ITEM_SIZE: f32 = 24.;
+const STAMINA_COLOR: Color = Color::rgb(0.6, 0.9, 0.3);
+const REWINDING_COLOR: Color = Color::rgb(0.5, 0.8, 1.);
 
 fn spawn_retry_ui(
     mut commands: Commands,
     font_assets: Res<FontAssets>,
     button_materials: Res<ButtonMaterials>,
+    route: Res<Route>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
     mut won_events: EventReader<WonEvent>,
 ) {
     if won_events.iter().last().is_some() {
+        commands
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.), Val::Auto),
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        top: Val::Px(60.),
+                        ..Default::default()
+                    },
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                material: materials.add(Color::NONE.into()),
+                ..Default::default()
+            })
+            .insert(Ui)
+            .insert(LevelEntity)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection {
+                            value: format!("Your way down: {}", route_name(&route.maps)),
+                            style: TextStyle {
+                                font_size: 30.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                font: font_assets.fira_sans.clone(),
+                            },
+                        }],
+                        alignment: Default::default(),
+                    },
+                    ..Default::default()
+                });
+            });
+        commands
+            .spawn_bundle(ButtonBundle {
+                style: Style {
+                    size: Size::new(Val::Px(150.0), Val::Px(50.0)),
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        right: Val::Px(20.),
+                        bottom: Val::Px(20.),
+                        ..Default::default()
+                    },
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                material: button_materials.normal.clone(),
+                ..Default::default()
+            })
+            .insert(RoutesButton)
+            .insert(Ui)
+            .insert(LevelEntity)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection {
+                            value: "Routes".to_string(),
+                            style: TextStyle {
+                                font_size: 30.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                font: font_assets.fira_sans.clone(),
+                            },
+                        }],
+                        alignment: Default::default(),
+                    },
+                    ..Default::default()
+                });
+            });
         commands
             .spawn_bundle(ButtonBundle {
                 style: Style {
@@ -47,6 +164,7 @@ fn spawn_retry_ui(
             })
             .insert(RetryButton)
             .insert(Ui)
+            .insert(LevelEntity)
             .with_children(|parent| {
                 parent.spawn_bundle(TextBundle {
                     text: Text {
@@ -74,7 +192,7 @@ fn click_retry_button(
     mut current_map: ResMut<Map>,
     mut interaction_query: Query<
         (&Interaction, &mut Handle<ColorMaterial>),
-        (Changed<Interaction>, With<Button>),
+        (Changed<Interaction>, With<RetryButton>),
     >,
     text_query: Query<Entity, (With<Ui>, Without<Acorn>)>,
     acorn: Query<Entity, (With<Acorn>, Without<Ui>)>,
@@ -100,3 +218,465 @@ fn click_retry_button(
         }
     }
 }
+
+fn click_routes_button(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    button_materials: Res<ButtonMaterials>,
+    explored: Res<ExploredRoutes>,
+    maps: Res<Assets<TiledMap>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut interaction_query: Query<
+        (&Interaction, &mut Handle<ColorMaterial>),
+        (Changed<Interaction>, With<RoutesButton>),
+    >,
+    depth_tree: Query<Entity, With<DepthTree>>,
+) {
+    for (interaction, mut material) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                if let Ok(tree) = depth_tree.single() {
+                    commands.entity(tree).despawn_recursive();
+                    continue;
+                }
+                spawn_depth_tree(
+                    &mut commands,
+                    &font_assets,
+                    &explored,
+                    &maps,
+                    &mut materials,
+                );
+            }
+            Interaction::Hovered => {
+                *material = button_materials.hovered.clone();
+            }
+            Interaction::None => {
+                *material = button_materials.normal.clone();
+            }
+        }
+    }
+}
+
+fn spawn_depth_tree(
+    commands: &mut Commands,
+    font_assets: &FontAssets,
+    explored: &ExploredRoutes,
+    maps: &Assets<TiledMap>,
+    materials: &mut Assets<ColorMaterial>,
+) {
+    let mut lines: Vec<(usize, String, Option<bool>)> = vec![];
+    let mut previous: Vec<Map> = vec![];
+    for route in all_routes(maps) {
+        let shared = route
+            .iter()
+            .zip(previous.iter())
+            .take_while(|(map, other)| map == other)
+            .count();
+        for (depth, map) in route.iter().enumerate().skip(shared) {
+            let leaf = depth == route.len() - 1;
+            lines.push((
+                depth,
+                map.name().to_string(),
+                if leaf {
+                    Some(explored.routes.contains(&route))
+                } else {
+                    None
+                },
+            ));
+        }
+        previous = route;
+    }
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(20.),
+                    top: Val::Px(110.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(10.)),
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.1, 0.1, 0.1, 0.8).into()),
+            ..Default::default()
+        })
+        .insert(DepthTree)
+        .insert(Ui)
+        .insert(LevelEntity)
+        .with_children(|parent| {
+            for (depth, name, explored) in lines {
+                let (value, color) = match explored {
+                    Some(true) => (format!("{} - explored", name), Color::rgb(0.9, 0.9, 0.9)),
+                    Some(false) => (format!("{} - unexplored", name), Color::rgb(1., 0.8, 0.3)),
+                    None => (name, Color::rgb(0.9, 0.9, 0.9)),
+                };
+                parent.spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect {
+                            left: Val::Px(depth as f32 * 20.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    text: Text {
+                        sections: vec![TextSection {
+                            value,
+                            style: TextStyle {
+                                font_size: 24.0,
+                                color,
+                                font: font_assets.fira_sans.clone(),
+                            },
+                        }],
+                        alignment: Default::default(),
+                    },
+                    ..Default::default()
+                });
+            }
+        });
+}
+
+fn spawn_inventory_hud(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::Row,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(InventoryHud);
+}
+
+fn update_inventory_hud(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    inventory: Query<&Inventory, (With<Player>, Changed<Inventory>)>,
+    hud: Query<(Entity, Option<&Children>), With<InventoryHud>>,
+) {
+    if let Ok(inventory) = inventory.single() {
+        if let Ok((hud, children)) = hud.single() {
+            if let Some(children) = children {
+                for child in children.iter() {
+                    commands.entity(*child).despawn();
+                }
+            }
+            commands.entity(hud).with_children(|parent| {
+                for item in inventory.items.iter() {
+                    parent.spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(HUD_ITEM_SIZE), Val::Px(HUD_ITEM_SIZE)),
+                            margin: Rect::all(Val::Px(4.)),
+                            ..Default::default()
+                        },
+                        material: materials.add(item.color().into()),
+                        ..Default::default()
+                    });
+                }
+            });
+        }
+    }
+}
+
+fn remove_inventory_hud(mut commands: Commands, hud: Query<Entity, With<InventoryHud>>) {
+    for entity in hud.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn spawn_digs_hud(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 30.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(DigsHud);
+}
+
+fn update_digs_hud(digs: Res<Digs>, mut hud: Query<&mut Text, With<DigsHud>>) {
+    if !digs.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = hud.single_mut() {
+        text.sections[0].value = if digs.left > 0 {
+            format!("Digs: {}", digs.left)
+        } else {
+            "".to_owned()
+        };
+    }
+}
+
+fn remove_digs_hud(mut commands: Commands, hud: Query<Entity, With<DigsHud>>) {
+    for entity in hud.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn spawn_stamina_hud(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    bottom: Val::Px(10.),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(150.), Val::Px(14.)),
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.1, 0.1, 0.1, 0.7).into()),
+            ..Default::default()
+        })
+        .insert(StaminaHud)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                        ..Default::default()
+                    },
+                    material: materials.add(STAMINA_COLOR.into()),
+                    ..Default::default()
+                })
+                .insert(StaminaBar);
+        });
+}
+
+fn update_stamina_hud(stamina: Res<Stamina>, mut bar: Query<&mut Style, With<StaminaBar>>) {
+    if !stamina.is_changed() {
+        return;
+    }
+    if let Ok(mut style) = bar.single_mut() {
+        style.size.width = Val::Percent(stamina.value * 100.);
+    }
+}
+
+fn remove_stamina_hud(mut commands: Commands, hud: Query<Entity, With<StaminaHud>>) {
+    for entity in hud.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn spawn_collectibles_hud(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(45.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 30.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(CollectiblesHud);
+}
+
+fn update_collectibles_hud(
+    score: Res<LevelScore>,
+    mut hud: Query<&mut Text, With<CollectiblesHud>>,
+) {
+    if !score.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = hud.single_mut() {
+        let value = if score.total > 0 {
+            format!("Acorns: {}/{}", score.collected, score.total)
+        } else {
+            "".to_owned()
+        };
+        // the level time ticks every frame, only touch the text if needed
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+fn remove_collectibles_hud(mut commands: Commands, hud: Query<Entity, With<CollectiblesHud>>) {
+    for entity in hud.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn spawn_rewind_hud(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(80.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 30.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(RewindHud);
+}
+
+fn update_rewind_hud(rewind: Res<Rewind>, mut hud: Query<&mut Text, With<RewindHud>>) {
+    if !rewind.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = hud.single_mut() {
+        let value = if rewind.budget.as_secs_f32() > 0. {
+            format!("Rewind: {:.1}s", rewind.budget.as_secs_f32())
+        } else {
+            "".to_owned()
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+        let color = if rewind.rewinding {
+            REWINDING_COLOR
+        } else {
+            Color::rgb(0.9, 0.9, 0.9)
+        };
+        if text.sections[0].style.color != color {
+            text.sections[0].style.color = color;
+        }
+    }
+}
+
+fn remove_rewind_hud(mut commands: Commands, hud: Query<Entity, With<RewindHud>>) {
+    for entity in hud.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn spawn_level_summary(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut summaries: EventReader<LevelSummary>,
+) {
+    let summary = match summaries.iter().last() {
+        Some(summary) => summary,
+        None => return,
+    };
+    let lines = vec![
+        format!("{} done!", summary.level),
+        format!("Stars: {} / 3", summary.result.stars),
+        format!(
+            "Acorns: {} / {}  (best {})",
+            summary.result.collected, summary.total, summary.best.collected
+        ),
+        format!(
+            "Time: {:.1}s  (best {:.1}s)",
+            summary.result.time.as_secs_f32(),
+            summary.best.time.as_secs_f32()
+        ),
+    ];
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Auto),
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(25.),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(LevelSummaryPanel)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        align_items: AlignItems::Center,
+                        padding: Rect::all(Val::Px(20.)),
+                        ..Default::default()
+                    },
+                    material: materials.add(Color::rgba(0.1, 0.1, 0.1, 0.8).into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for (index, line) in lines.into_iter().enumerate() {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                line,
+                                TextStyle {
+                                    font: font_assets.fira_sans.clone(),
+                                    font_size: if index == 0 { 40.0 } else { 28.0 },
+                                    color: Color::rgb(0.9, 0.9, 0.9),
+                                },
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        });
+                    }
+                });
+        });
+}
+
+fn remove_level_summary(
+    commands: Commands,
+    current_map: Res<Map>,
+    panels: Query<Entity, With<LevelSummaryPanel>>,
+) {
+    if current_map.is_changed() {
+        remove_all_level_summaries(commands, panels);
+    }
+}
+
+fn remove_all_level_summaries(
+    mut commands: Commands,
+    panels: Query<Entity, With<LevelSummaryPanel>>,
+) {
+    for entity in panels.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,537 @@
+use crate::audio::AudioEffect;
+use crate::blocks::Block;
+use crate::loading::{AudioAssets, FontAssets, TextureAssets};
+use crate::map::{is_touching, Collide, LevelEntity, Map, MapSystemLabels, Slot, TILE_SIZE};
+use crate::player::{calc_camera_position, Player};
+use crate::scenes::{CutScene, TriggerScene};
+use crate::{GameData, GameState};
+use bevy::prelude::*;
+use bevy::utils::Duration;
+use serde::Deserialize;
+
+pub const COUNTDOWN_Z: f32 = 6.;
+
+pub const BUTTON_TINT: Color = Color::WHITE;
+pub const PRESSURE_PLATE_TINT: Color = Color::rgb(0.6, 0.8, 1.);
+pub const TIMED_BUTTON_TINT: Color = Color::rgb(1., 0.85, 0.5);
+pub const LEVER_TINT: Color = Color::rgb(0.6, 1., 0.6);
+
+#[derive(SystemLabel, Clone, Hash, Debug, Eq, PartialEq)]
+pub enum WiringSystemLabels {
+    Switches,
+    Walls,
+}
+
+pub struct WiringPlugin;
+
+impl Plugin for WiringPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<RollBackSwitches>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .label(WiringSystemLabels::Switches)
+                    .after(MapSystemLabels::DrawMap)
+                    .with_system(check_buttons.system())
+                    .with_system(check_levers.system())
+                    .with_system(check_pressure_plates.system())
+                    .with_system(tick_wall_timers.system())
+                    .with_system(roll_back_switches.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .after(WiringSystemLabels::Switches)
+                    .label(WiringSystemLabels::Walls)
+                    .with_system(propagate_signals.system()),
+            );
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub enum Gate {
+    Input(usize),
+    And(Vec<Gate>),
+    Or(Vec<Gate>),
+    Not(Box<Gate>),
+}
+
+impl Gate {
+    pub fn evaluate(&self, signals: &[bool]) -> bool {
+        match self {
+            Gate::Input(index) => *signals.get(*index).unwrap_or(&false),
+            Gate::And(gates) => gates.iter().all(|gate| gate.evaluate(signals)),
+            Gate::Or(gates) => gates.iter().any(|gate| gate.evaluate(signals)),
+            Gate::Not(gate) => !gate.evaluate(signals),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SwitchKind {
+    Button,
+    PressurePlate,
+    TimedButton,
+    Lever,
+}
+
+impl SwitchKind {
+    pub fn tint(&self) -> Color {
+        match self {
+            SwitchKind::Button => BUTTON_TINT,
+            SwitchKind::PressurePlate => PRESSURE_PLATE_TINT,
+            SwitchKind::TimedButton => TIMED_BUTTON_TINT,
+            SwitchKind::Lever => LEVER_TINT,
+        }
+    }
+}
+
+pub struct Switch {
+    pub index: usize,
+    pub kind: SwitchKind,
+    pub active: bool,
+}
+
+impl Switch {
+    fn announces(&self) -> bool {
+        match self.kind {
+            SwitchKind::Button | SwitchKind::Lever => true,
+            SwitchKind::TimedButton => self.active,
+            SwitchKind::PressurePlate => false,
+        }
+    }
+
+    // Only buttons and levers keep their state over time
+    pub fn lasting_signal(&self) -> bool {
+        match self.kind {
+            SwitchKind::Button | SwitchKind::Lever => self.active,
+            SwitchKind::TimedButton | SwitchKind::PressurePlate => false,
+        }
+    }
+}
+
+pub struct Wall {
+    pub gate: Gate,
+    pub open: bool,
+    pub slot: Slot,
+}
+
+pub struct Trigger;
+
+pub struct PressurePlate;
+
+pub struct TimedButton {
+    pub duration: Duration,
+}
+
+pub struct Lever {
+    pub touching: bool,
+}
+
+pub struct WallTimer {
+    timer: Timer,
+    countdown: Entity,
+    seconds_left: u64,
+}
+
+pub struct Countdown;
+
+// Restore buttons and levers to the given signals; timers and plates are released
+pub struct RollBackSwitches {
+    pub signals: Vec<bool>,
+}
+
+pub fn switch_material(kind: SwitchKind, active: bool, textures: &TextureAssets) -> ColorMaterial {
+    let texture = if active {
+        textures.texture_button_down.clone()
+    } else {
+        textures.texture_button_up.clone()
+    };
+    ColorMaterial::modulated_texture(texture, kind.tint())
+}
+
+pub fn apply_wall_state(
+    commands: &mut Commands,
+    entity: Entity,
+    wall: &Wall,
+    material: &mut Handle<ColorMaterial>,
+    materials: &mut Assets<ColorMaterial>,
+    textures: &TextureAssets,
+) {
+    if wall.open {
+        *material = materials.add(textures.texture_wall_down.clone().into());
+        commands.entity(entity).remove::<Collide>();
+    } else {
+        *material = materials.add(textures.texture_wall_up.clone().into());
+        commands.entity(entity).insert(Collide {
+            x: wall.slot.column,
+            y: wall.slot.row,
+        });
+    }
+}
+
+fn check_buttons(
+    mut commands: Commands,
+    game_state: Res<GameData>,
+    font_assets: Res<FontAssets>,
+    textures: Res<TextureAssets>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut buttons: Query<
+        (
+            Entity,
+            &Transform,
+            &mut Switch,
+            &mut Handle<ColorMaterial>,
+            Option<&TimedButton>,
+        ),
+        With<Trigger>,
+    >,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    if let Ok(player_transform) = player_query.single() {
+        for (entity, transform, mut switch, mut material, timed) in buttons.iter_mut() {
+            if !is_touching(player_transform, transform) {
+                continue;
+            }
+            commands.entity(entity).remove::<Trigger>();
+            switch.active = true;
+            *material = materials.add(switch_material(switch.kind, true, &textures));
+            audio_effect.send(AudioEffect {
+                handle: audio_assets.button_click.clone(),
+            });
+
+            if let Some(TimedButton { duration }) = timed {
+                let countdown = commands
+                    .spawn_bundle(Text2dBundle {
+                        text: Text::with_section(
+                            duration.as_secs().to_string(),
+                            TextStyle {
+                                font: font_assets.fira_sans.clone(),
+                                font_size: 30.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                            },
+                            TextAlignment {
+                                vertical: VerticalAlign::Center,
+                                horizontal: HorizontalAlign::Center,
+                            },
+                        ),
+                        transform: Transform::from_translation(Vec3::new(
+                            transform.translation.x,
+                            transform.translation.y + TILE_SIZE * 0.6,
+                            COUNTDOWN_Z,
+                        )),
+                        ..Default::default()
+                    })
+                    .insert(Countdown)
+                    .insert(LevelEntity)
+                    .id();
+                commands.entity(entity).insert(WallTimer {
+                    timer: Timer::new(*duration, false),
+                    countdown,
+                    seconds_left: duration.as_secs(),
+                });
+            }
+        }
+    }
+}
+
+fn check_levers(
+    game_state: Res<GameData>,
+    textures: Res<TextureAssets>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut levers: Query<(
+        &Transform,
+        &mut Switch,
+        &mut Lever,
+        &mut Handle<ColorMaterial>,
+    )>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    if let Ok(player_transform) = player_query.single() {
+        for (transform, mut switch, mut lever, mut material) in levers.iter_mut() {
+            let touching = is_touching(player_transform, transform);
+            if touching == lever.touching {
+                continue;
+            }
+            lever.touching = touching;
+            if touching {
+                switch.active = !switch.active;
+                *material = materials.add(switch_material(switch.kind, switch.active, &textures));
+                audio_effect.send(AudioEffect {
+                    handle: audio_assets.button_click.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn check_pressure_plates(
+    game_state: Res<GameData>,
+    textures: Res<TextureAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut plates: Query<(&Transform, &mut Switch, &mut Handle<ColorMaterial>), With<PressurePlate>>,
+    weights: Query<&Transform, Or<(With<Player>, With<Block>)>>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    for (transform, mut switch, mut material) in plates.iter_mut() {
+        let pressed = weights.iter().any(|weight| is_touching(weight, transform));
+        if pressed == switch.active {
+            continue;
+        }
+        switch.active = pressed;
+        *material = materials.add(switch_material(switch.kind, pressed, &textures));
+    }
+}
+
+fn tick_wall_timers(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_state: Res<GameData>,
+    textures: Res<TextureAssets>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut buttons: Query<(
+        Entity,
+        &mut Switch,
+        &mut WallTimer,
+        &mut Handle<ColorMaterial>,
+    )>,
+    mut countdowns: Query<&mut Text, With<Countdown>>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    for (button, mut switch, mut wall_timer, mut material) in buttons.iter_mut() {
+        wall_timer.timer.tick(time.delta());
+        let seconds_left = (wall_timer.timer.duration() - wall_timer.timer.elapsed())
+            .as_secs_f32()
+            .ceil() as u64;
+        if seconds_left < wall_timer.seconds_left {
+            wall_timer.seconds_left = seconds_left;
+            if let Ok(mut text) = countdowns.get_mut(wall_timer.countdown) {
+                text.sections[0].value = seconds_left.to_string();
+            }
+            if seconds_left > 0 {
+                audio_effect.send(AudioEffect {
+                    handle: audio_assets.tick.clone(),
+                });
+            }
+        }
+        if !wall_timer.timer.finished() {
+            continue;
+        }
+        switch.active = false;
+        *material = materials.add(switch_material(switch.kind, false, &textures));
+        commands.entity(wall_timer.countdown).despawn();
+        commands
+            .entity(button)
+            .remove::<WallTimer>()
+            .insert(Trigger);
+    }
+}
+
+fn propagate_signals(
+    mut commands: Commands,
+    current_map: Res<Map>,
+    game_state: Res<GameData>,
+    windows: Res<Windows>,
+    textures: Res<TextureAssets>,
+    audio_assets: Res<AudioAssets>,
+    mut audio_effect: EventWriter<AudioEffect>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut trigger_scene: EventWriter<TriggerScene>,
+    switches: Query<&Switch>,
+    changed_switches: Query<&Switch, Changed<Switch>>,
+    mut walls: Query<(Entity, &mut Wall, &mut Handle<ColorMaterial>)>,
+    player_query: Query<&Transform, With<Player>>,
+    blocks: Query<&Block>,
+) {
+    if game_state.frozen {
+        return;
+    }
+    let mut signals = vec![false; current_map.active_elements().len()];
+    for switch in switches.iter() {
+        if let Some(signal) = signals.get_mut(switch.index) {
+            *signal = switch.active;
+        }
+    }
+    let player_slot = player_query.single().ok().map(|transform| {
+        current_map.slot_from_position(transform.translation.x, transform.translation.y)
+    });
+    let mut changed_walls: Vec<(Entity, Slot, bool)> = vec![];
+    for (entity, wall, _material) in walls.iter_mut() {
+        let open = wall.gate.evaluate(&signals);
+        if open == wall.open {
+            continue;
+        }
+        // never close a wall on top of the squirrel or a block
+        if !open
+            && (player_slot.as_ref() == Some(&wall.slot)
+                || blocks.iter().any(|block| block.slot == wall.slot))
+        {
+            continue;
+        }
+        changed_walls.push((entity, wall.slot.clone(), open));
+    }
+    if changed_walls.is_empty() {
+        return;
+    }
+
+    if changed_switches.iter().any(|switch| switch.announces()) {
+        if let Ok(player_transform) = player_query.single() {
+            let window = windows.get_primary().expect("No primary window");
+            let wall_position = (
+                changed_walls[0].1.column as f32 * TILE_SIZE,
+                changed_walls[0].1.row as f32 * TILE_SIZE,
+            );
+            // the walls keep their state until the scene swaps them; should the scene
+            // get dropped, the next evaluation finds them unchanged and tries again
+            trigger_scene.send(TriggerScene {
+                scene: CutScene::ActivateButton {
+                    walls: changed_walls
+                        .iter()
+                        .map(|(entity, _slot, open)| (*entity, *open))
+                        .collect(),
+                    camera_from: calc_camera_position(
+                        player_transform.translation.x,
+                        player_transform.translation.y,
+                        window,
+                        &current_map.dimensions(),
+                    ),
+                    camera_to: calc_camera_position(
+                        wall_position.0,
+                        wall_position.1,
+                        window,
+                        &current_map.dimensions(),
+                    ),
+                },
+            });
+            return;
+        }
+    }
+
+    for (entity, _slot, open) in changed_walls {
+        if let Ok((entity, mut wall, mut material)) = walls.get_mut(entity) {
+            wall.open = open;
+            apply_wall_state(
+                &mut commands,
+                entity,
+                &wall,
+                &mut material,
+                &mut materials,
+                &textures,
+            );
+        }
+    }
+    audio_effect.send(AudioEffect {
+        handle: audio_assets.wall_moving.clone(),
+    });
+}
+
+fn roll_back_switches(
+    mut commands: Commands,
+    current_map: Res<Map>,
+    textures: Res<TextureAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut roll_back_events: EventReader<RollBackSwitches>,
+    mut switches: Query<
+        (
+            Entity,
+            &mut Switch,
+            &mut Handle<ColorMaterial>,
+            Option<&WallTimer>,
+        ),
+        Without<Wall>,
+    >,
+    mut walls: Query<(Entity, &mut Wall, &mut Handle<ColorMaterial>), Without<Switch>>,
+    player_query: Query<&Transform, With<Player>>,
+    blocks: Query<&Block>,
+) {
+    let signals = match roll_back_events.iter().last() {
+        Some(roll_back) => roll_back.signals.clone(),
+        None => return,
+    };
+    for (entity, mut switch, mut material, wall_timer) in switches.iter_mut() {
+        let active = match switch.kind {
+            SwitchKind::Button | SwitchKind::Lever => *signals.get(switch.index).unwrap_or(&false),
+            SwitchKind::TimedButton | SwitchKind::PressurePlate => false,
+        };
+        if let Some(wall_timer) = wall_timer {
+            commands.entity(wall_timer.countdown).despawn();
+            commands.entity(entity).remove::<WallTimer>();
+        }
+        if switch.kind == SwitchKind::Button || switch.kind == SwitchKind::TimedButton {
+            if active {
+                commands.entity(entity).remove::<Trigger>();
+            } else {
+                commands.entity(entity).insert(Trigger);
+            }
+        }
+        if switch.active != active {
+            switch.active = active;
+            *material = materials.add(switch_material(switch.kind, active, &textures));
+        }
+    }
+    // apply the walls right away, so the rollback does not play a wall scene
+    let player_slot = player_query.single().ok().map(|transform| {
+        current_map.slot_from_position(transform.translation.x, transform.translation.y)
+    });
+    for (entity, mut wall, mut material) in walls.iter_mut() {
+        let open = wall.gate.evaluate(&signals);
+        if open == wall.open
+            || (!open
+                && (player_slot.as_ref() == Some(&wall.slot)
+                    || blocks.iter().any(|block| block.slot == wall.slot)))
+        {
+            continue;
+        }
+        wall.open = open;
+        apply_wall_state(
+            &mut commands,
+            entity,
+            &wall,
+            &mut material,
+            &mut materials,
+            &textures,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluates_gates() {
+        let signals = [true, false, true];
+        assert!(Gate::Input(0).evaluate(&signals));
+        assert!(!Gate::Input(1).evaluate(&signals));
+        // missing inputs are off
+        assert!(!Gate::Input(3).evaluate(&signals));
+
+        assert!(Gate::And(vec![Gate::Input(0), Gate::Input(2)]).evaluate(&signals));
+        assert!(!Gate::And(vec![Gate::Input(0), Gate::Input(1)]).evaluate(&signals));
+        assert!(Gate::Or(vec![Gate::Input(1), Gate::Input(2)]).evaluate(&signals));
+        assert!(!Gate::Or(vec![Gate::Input(1), Gate::Input(3)]).evaluate(&signals));
+        assert!(Gate::Not(Box::new(Gate::Input(1))).evaluate(&signals));
+        assert!(!Gate::Not(Box::new(Gate::Input(0))).evaluate(&signals));
+
+        let nested = Gate::Or(vec![
+            Gate::And(vec![Gate::Input(0), Gate::Input(1)]),
+            Gate::Not(Box::new(Gate::Input(2))),
+        ]);
+        assert!(!nested.evaluate(&signals));
+        assert!(nested.evaluate(&[false, false, false]));
+    }
+}